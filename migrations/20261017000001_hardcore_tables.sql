@@ -0,0 +1,40 @@
+-- The tables the Hardcore Spigot Plugin creates. They may already exist when the
+-- plugin has been writing to this database, so nothing here can assume they don't.
+
+CREATE TABLE IF NOT EXISTS players (
+    player_uuid uuid PRIMARY KEY,
+    name text NOT NULL,
+    joined timestamptz NOT NULL DEFAULT now(),
+    playtime integer NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS deaths (
+    id bigserial PRIMARY KEY,
+    player_uuid uuid NOT NULL,
+    stamp timestamptz NOT NULL DEFAULT now(),
+    playtime integer NOT NULL DEFAULT 0,
+    reason text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS extra_lives (
+    id bigserial PRIMARY KEY,
+    player_uuid uuid NOT NULL,
+    stamp timestamptz NOT NULL DEFAULT now(),
+    reason text NOT NULL,
+    playtime integer NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS offenses (
+    id bigserial PRIMARY KEY,
+    player_uuid uuid NOT NULL,
+    stamp timestamptz NOT NULL DEFAULT now(),
+    reason text NOT NULL,
+    playtime integer NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS deaths_player_uuid_idx ON deaths (player_uuid);
+CREATE INDEX IF NOT EXISTS deaths_stamp_idx ON deaths (stamp);
+CREATE INDEX IF NOT EXISTS extra_lives_player_uuid_idx ON extra_lives (player_uuid);
+CREATE INDEX IF NOT EXISTS extra_lives_stamp_idx ON extra_lives (stamp);
+CREATE INDEX IF NOT EXISTS offenses_player_uuid_idx ON offenses (player_uuid);
+CREATE INDEX IF NOT EXISTS offenses_stamp_idx ON offenses (stamp);
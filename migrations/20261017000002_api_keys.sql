@@ -0,0 +1,9 @@
+-- Only the sha256 hash of each key is stored.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id bigserial PRIMARY KEY,
+    name text NOT NULL,
+    key_hash text NOT NULL UNIQUE,
+    scopes text[] NOT NULL,
+    created timestamptz NOT NULL DEFAULT now(),
+    revoked boolean NOT NULL DEFAULT false
+);
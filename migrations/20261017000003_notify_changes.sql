@@ -0,0 +1,27 @@
+-- Tells the api about new rows with NOTIFY, so it can push them to the live event feed.
+CREATE OR REPLACE FUNCTION hardcore_notify_change() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('hardcore_changes', json_build_object(
+        'kind', TG_TABLE_NAME,
+        'player_uuid', NEW.player_uuid,
+        'id', CASE WHEN TG_TABLE_NAME = 'players' THEN NULL ELSE (to_jsonb(NEW) ->> 'id')::bigint END
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS players_notify_change ON players;
+CREATE TRIGGER players_notify_change AFTER INSERT ON players
+    FOR EACH ROW EXECUTE FUNCTION hardcore_notify_change();
+
+DROP TRIGGER IF EXISTS deaths_notify_change ON deaths;
+CREATE TRIGGER deaths_notify_change AFTER INSERT ON deaths
+    FOR EACH ROW EXECUTE FUNCTION hardcore_notify_change();
+
+DROP TRIGGER IF EXISTS extra_lives_notify_change ON extra_lives;
+CREATE TRIGGER extra_lives_notify_change AFTER INSERT ON extra_lives
+    FOR EACH ROW EXECUTE FUNCTION hardcore_notify_change();
+
+DROP TRIGGER IF EXISTS offenses_notify_change ON offenses;
+CREATE TRIGGER offenses_notify_change AFTER INSERT ON offenses
+    FOR EACH ROW EXECUTE FUNCTION hardcore_notify_change();
@@ -0,0 +1,20 @@
+-- Subscribers that are sent live events, an empty event_types gets every event.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id bigserial PRIMARY KEY,
+    url text NOT NULL,
+    secret text NOT NULL,
+    event_types text[] NOT NULL DEFAULT '{}',
+    created timestamptz NOT NULL DEFAULT now()
+);
+
+-- Deliveries that still failed after every retry.
+CREATE TABLE IF NOT EXISTS webhook_dead_letters (
+    id bigserial PRIMARY KEY,
+    webhook_id bigint NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    payload text NOT NULL,
+    error text NOT NULL,
+    attempts integer NOT NULL,
+    created timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS webhook_dead_letters_webhook_id_idx ON webhook_dead_letters (webhook_id);
@@ -0,0 +1,8 @@
+-- A window of time timelines can be limited to, rules overrides the server's rule set when set.
+CREATE TABLE IF NOT EXISTS seasons (
+    id bigserial PRIMARY KEY,
+    name text NOT NULL UNIQUE,
+    starts timestamptz NOT NULL,
+    ends timestamptz,
+    rules jsonb
+);
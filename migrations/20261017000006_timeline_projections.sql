@@ -0,0 +1,43 @@
+-- Timelines built by the api, kept up to date whenever a player's rows change so other tools can
+-- read the spans with SQL. Everything here can be rebuilt from the other tables with `HardcoreApi rebuild`.
+
+CREATE TABLE IF NOT EXISTS player_timeline_stats (
+    player_uuid uuid PRIMARY KEY,
+    player_name text NOT NULL,
+    player_state text NOT NULL,
+    survived_seconds integer NOT NULL,
+    longest_life_seconds integer NOT NULL,
+    shortest_life_seconds integer NOT NULL,
+    ranked_lives_remaining integer NOT NULL,
+    unranked_lives_remaining integer NOT NULL,
+    deaths integer NOT NULL,
+    offenses integer NOT NULL,
+    built timestamptz NOT NULL DEFAULT now()
+);
+
+-- The leaderboard is paged by postgres when projections are on, so every sort has an index.
+CREATE INDEX IF NOT EXISTS player_timeline_stats_survived_idx ON player_timeline_stats (survived_seconds DESC);
+CREATE INDEX IF NOT EXISTS player_timeline_stats_longest_life_idx ON player_timeline_stats (longest_life_seconds DESC);
+CREATE INDEX IF NOT EXISTS player_timeline_stats_shortest_life_idx ON player_timeline_stats (shortest_life_seconds);
+CREATE INDEX IF NOT EXISTS player_timeline_stats_deaths_idx ON player_timeline_stats (deaths DESC);
+CREATE INDEX IF NOT EXISTS player_timeline_stats_offenses_idx ON player_timeline_stats (offenses DESC);
+CREATE INDEX IF NOT EXISTS player_timeline_stats_state_idx ON player_timeline_stats (player_state);
+
+-- The events the same as the api returns them, playtime and span are in seconds.
+CREATE TABLE IF NOT EXISTS timeline_events (
+    player_uuid uuid NOT NULL,
+    position integer NOT NULL,
+    event_id bigint,
+    stamp timestamptz NOT NULL,
+    what text NOT NULL,
+    context text NOT NULL,
+    playtime integer NOT NULL,
+    span integer NOT NULL,
+    unranked boolean NOT NULL,
+    no_lives boolean NOT NULL,
+    ranked_lives_after integer NOT NULL,
+    unranked_lives_after integer NOT NULL,
+    PRIMARY KEY (player_uuid, position)
+);
+
+CREATE INDEX IF NOT EXISTS timeline_events_what_stamp_idx ON timeline_events (what, stamp);
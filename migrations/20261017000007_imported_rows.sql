@@ -0,0 +1,17 @@
+-- Rows written by an import are marked, so their changes aren't sent as live events.
+ALTER TABLE players ADD COLUMN IF NOT EXISTS imported boolean NOT NULL DEFAULT false;
+ALTER TABLE deaths ADD COLUMN IF NOT EXISTS imported boolean NOT NULL DEFAULT false;
+ALTER TABLE extra_lives ADD COLUMN IF NOT EXISTS imported boolean NOT NULL DEFAULT false;
+ALTER TABLE offenses ADD COLUMN IF NOT EXISTS imported boolean NOT NULL DEFAULT false;
+
+CREATE OR REPLACE FUNCTION hardcore_notify_change() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('hardcore_changes', json_build_object(
+        'kind', TG_TABLE_NAME,
+        'player_uuid', NEW.player_uuid,
+        'id', CASE WHEN TG_TABLE_NAME = 'players' THEN NULL ELSE (to_jsonb(NEW) ->> 'id')::bigint END,
+        'imported', NEW.imported
+    )::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::errors::Error;
+use crate::rules::RuleSet;
+use crate::store::Store;
+use crate::timeline::{EventType, Timeline, TimelineEvent};
+use crate::types::death::Death;
+use crate::types::extra_life::ExtraLife;
+use crate::types::offense::Offense;
+use crate::types::player::Player;
+
+/// How much an anomaly changes a player's timeline.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The timeline works around it, but the row is missing data.
+    Info,
+    /// The spans around the row are probably wrong.
+    Warning,
+    /// The row can't be counted the way the plugin meant it.
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A death when the player had no lives left.
+    DeathWithNoLives,
+    /// The row has less playtime than a row before it.
+    PlaytimeWentBackwards,
+    /// An extra life with a playtime of 0, the plugin didn't know the playtime.
+    ExtraLifeWithoutPlaytime,
+    /// The row happened before the player joined.
+    BeforeJoined,
+    /// The player's playtime is less than the playtime of one of their rows.
+    PlaytimeBehindEvents,
+}
+
+/// Something wrong with a player's rows that a moderator should look at.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    pub player_uuid: Uuid,
+    pub player_name: String,
+    pub kind: AnomalyKind,
+    pub severity: Severity,
+    /// The row the anomaly is on, empty when it is on the player.
+    pub what: Option<EventType>,
+    pub id: Option<i64>,
+    pub stamp: DateTime<Utc>,
+    /// In ticks, the same as the row.
+    pub playtime: i32,
+    pub message: String,
+}
+
+/// Checks every player's rows, the anomalies are ordered by player and then by when they happened.
+pub async fn check_all(store: &dyn Store, rules: &RuleSet) -> Result<Vec<Anomaly>, Error> {
+    let mut players = store.get_players().await?;
+    let mut deaths = store.get_deaths().await?;
+    let mut offenses = store.get_offenses().await?;
+    let mut extra_lives = store.get_extra_lives().await?;
+
+    players.sort_by_key(|p| p.name.to_lowercase());
+
+    let mut anomalies = vec![];
+    for player in players {
+        anomalies.extend(check_player(
+            &player,
+            deaths.remove(&player.player_uuid).unwrap_or_default(),
+            offenses.remove(&player.player_uuid).unwrap_or_default(),
+            extra_lives.remove(&player.player_uuid).unwrap_or_default(),
+            rules,
+        ));
+    }
+
+    Ok(anomalies)
+}
+
+/// Runs every check over one player's rows.
+pub fn check_player(
+    player: &Player,
+    deaths: Vec<Death>,
+    offenses: Vec<Offense>,
+    extra_lives: Vec<ExtraLife>,
+    rules: &RuleSet,
+) -> Vec<Anomaly> {
+    let events = Timeline::events_from_parts(&deaths, &offenses, &extra_lives);
+    let anomaly = |kind: AnomalyKind, severity: Severity, event: &TimelineEvent, message: String| Anomaly {
+        player_uuid: player.player_uuid,
+        player_name: player.name.clone(),
+        kind,
+        severity,
+        what: Some(event.what),
+        id: event.id,
+        stamp: event.stamp,
+        playtime: event.playtime,
+        message,
+    };
+
+    let mut anomalies = vec![];
+    let mut max_playtime = 0;
+    for event in &events {
+        if event.stamp < player.joined {
+            anomalies.push(anomaly(AnomalyKind::BeforeJoined, Severity::Warning, event,
+                format!("{} is before the player joined at {}", event.what, player.joined.to_rfc3339())));
+        }
+
+        if event.what == EventType::ExtraLife && event.playtime == 0 {
+            anomalies.push(anomaly(AnomalyKind::ExtraLifeWithoutPlaytime, Severity::Info, event,
+                "extra life has no playtime".to_string()));
+            // the timeline skips it when working out spans, so it can't go backwards.
+            continue;
+        }
+
+        if event.playtime < max_playtime {
+            anomalies.push(anomaly(AnomalyKind::PlaytimeWentBackwards, Severity::Warning, event,
+                format!("{} has a playtime of {} after an event with {}", event.what, event.playtime, max_playtime)));
+        }
+        max_playtime = max_playtime.max(event.playtime);
+    }
+
+    if player.playtime < max_playtime {
+        anomalies.push(Anomaly {
+            player_uuid: player.player_uuid,
+            player_name: player.name.clone(),
+            kind: AnomalyKind::PlaytimeBehindEvents,
+            severity: Severity::Warning,
+            what: None,
+            id: None,
+            stamp: player.joined,
+            playtime: player.playtime,
+            message: format!("the player has a playtime of {} but has an event with {}", player.playtime, max_playtime),
+        });
+    }
+
+    // the timeline is what decides if the player had a life to lose.
+    let timeline = Timeline::build_from_parts(player, deaths, offenses, extra_lives, rules, None);
+    for died in timeline.events.iter().filter(|e| e.no_lives) {
+        if let Some(event) = events.iter().find(|e| e.what == EventType::Died && e.id == died.id) {
+            anomalies.push(anomaly(AnomalyKind::DeathWithNoLives, Severity::Error, event,
+                "the player died with no lives left".to_string()));
+        }
+    }
+
+    anomalies.sort_by(|a, b| a.stamp.cmp(&b.stamp).then_with(|| b.severity.cmp(&a.severity)));
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{at, death, extra_life, player};
+    use super::*;
+
+    fn kinds(anomalies: &[Anomaly]) -> Vec<(AnomalyKind, Option<i64>)> {
+        anomalies.iter().map(|a| (a.kind, a.id)).collect()
+    }
+
+    #[test]
+    fn clean_rows_have_no_anomalies() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(20), 4000)];
+
+        assert_eq!(check_player(&player(), deaths, vec![], vec![], &RuleSet::default()), vec![]);
+    }
+
+    #[test]
+    fn a_death_with_no_lives_is_an_error() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(20), 4000)];
+        let rules = RuleSet { starting_lives: 1, ..RuleSet::default() };
+
+        let anomalies = check_player(&player(), deaths, vec![], vec![], &rules);
+
+        assert_eq!(kinds(&anomalies), vec![(AnomalyKind::DeathWithNoLives, Some(2))]);
+        assert_eq!(anomalies[0].severity, Severity::Error);
+        // the playtime of the row, not the timeline's seconds.
+        assert_eq!(anomalies[0].playtime, 4000);
+    }
+
+    #[test]
+    fn playtime_going_backwards_is_a_warning() {
+        let deaths = vec![death(1, at(10), 4000), death(2, at(20), 2000)];
+
+        let anomalies = check_player(&player(), deaths, vec![], vec![], &RuleSet::default());
+
+        assert_eq!(kinds(&anomalies), vec![(AnomalyKind::PlaytimeWentBackwards, Some(2))]);
+    }
+
+    #[test]
+    fn an_extra_life_without_playtime_is_only_info() {
+        let deaths = vec![death(1, at(10), 2000)];
+        let extra_lives = vec![extra_life(2, at(20), 0)];
+
+        let anomalies = check_player(&player(), deaths, vec![], extra_lives, &RuleSet::default());
+
+        assert_eq!(kinds(&anomalies), vec![(AnomalyKind::ExtraLifeWithoutPlaytime, Some(2))]);
+        assert_eq!(anomalies[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn events_before_joining_and_after_the_players_playtime() {
+        let deaths = vec![death(1, at(-10), 2000), death(2, at(20), 200_000)];
+
+        let anomalies = check_player(&player(), deaths, vec![], vec![], &RuleSet::default());
+
+        assert_eq!(kinds(&anomalies), vec![
+            (AnomalyKind::BeforeJoined, Some(1)),
+            (AnomalyKind::PlaytimeBehindEvents, None),
+        ]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+use warp::{Filter, Rejection};
+use crate::errors::Error;
+use crate::store::DynStore;
+
+/// What an api key is allowed to do. Admin keys can do everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Ingest,
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "ingest" => Ok(Scope::Ingest),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!("unknown scope '{}'", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Ingest => write!(f, "ingest"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// An api key as it is stored, only the hash of the key is kept.
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created: DateTime<Utc>,
+}
+
+impl ApiKey {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Creates a new random key, this is only ever shown once to the admin that created it.
+pub fn generate_key() -> String {
+    format!("hc_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[derive(Clone, Debug)]
+pub struct Auth {
+    store: DynStore,
+    /// The hash of the ADMIN_API_KEY env variable, used to create the first keys.
+    admin_key_hash: Option<String>,
+    /// When true read endpoints don't need a key.
+    public_read: bool,
+}
+
+impl Auth {
+    pub fn new(store: DynStore, admin_key: Option<String>, public_read: bool) -> Self {
+        Auth {
+            store,
+            admin_key_hash: admin_key.map(|key| hash_key(&key)),
+            public_read,
+        }
+    }
+
+    /// Only lets a request through when it has a key with the scope. The key can be in an
+    /// `Authorization: Bearer <key>` or an `X-Api-Key: <key>` header.
+    pub fn require(&self, scope: Scope) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let auth = self.clone();
+        warp::header::optional::<String>("authorization")
+            .and(warp::header::optional::<String>("x-api-key"))
+            .and_then(move |authorization: Option<String>, api_key: Option<String>| {
+                let auth = auth.clone();
+                async move {
+                    if scope == Scope::Read && auth.public_read {
+                        return Ok(());
+                    }
+
+                    let key = authorization
+                        .as_deref()
+                        .and_then(|h| h.strip_prefix("Bearer "))
+                        .or(api_key.as_deref());
+                    let key = match key {
+                        Some(key) => key,
+                        None => return Err(warp::reject::custom(Error::Unauthorized)),
+                    };
+
+                    auth.check(key, scope).await.map_err(warp::reject::custom)
+                }
+            })
+            .untuple_one()
+    }
+
+    async fn check(&self, key: &str, scope: Scope) -> Result<(), Error> {
+        let hash = hash_key(key);
+        // compared in constant time so the time taken doesn't give away how much of it matched.
+        if let Some(admin_key_hash) = &self.admin_key_hash {
+            if bool::from(admin_key_hash.as_bytes().ct_eq(hash.as_bytes())) {
+                return Ok(());
+            }
+        }
+
+        match self.store.get_api_key(&hash).await? {
+            Some(api_key) if api_key.allows(scope) => Ok(()),
+            Some(_) => Err(Error::Forbidden),
+            None => Err(Error::Unauthorized),
+        }
+    }
+}
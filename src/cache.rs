@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{event, Level};
+use uuid::Uuid;
+use crate::config::env_or;
+use crate::errors::Error;
+use crate::live::Hub;
+use crate::rules::RuleSet;
+use crate::store::Store;
+use crate::timeline::Timeline;
+use crate::types::player::Player;
+use crate::types::season::Season;
+
+/// How long a timeline is used for when nothing says it changed. The alive event's span grows
+/// while the player plays, so even a player with no new rows has to be rebuilt now and then.
+const DEFAULT_TTL_SECONDS: u64 = 60;
+
+/// With more players missing than this the batched loaders are used instead of a query per player.
+const BATCH_AFTER: usize = 8;
+
+/// A player's timeline, for the whole server or for a season.
+type Key = (Uuid, Option<i64>);
+
+struct Entry {
+    /// The player row the timeline was built from, a different row means it is out of date.
+    player: Player,
+    timeline: Timeline,
+    built: Instant,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Built timelines, so every request doesn't rebuild every timeline. A player's timelines are
+/// dropped when they get a new row, or when the ttl runs out.
+pub struct TimelineCache {
+    entries: RwLock<HashMap<Key, Entry>>,
+    /// A ttl of zero turns the cache off.
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Goes up on every invalidation, a timeline built while it changed might be out of date
+    /// already so it isn't kept.
+    generation: AtomicU64,
+}
+
+impl fmt::Debug for TimelineCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimelineCache")
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl TimelineCache {
+    pub fn new(ttl: Duration) -> Self {
+        TimelineCache {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// TIMELINE_CACHE_TTL_SECONDS, 0 turns the cache off.
+    pub fn from_env() -> Self {
+        Self::new(Duration::from_secs(env_or("TIMELINE_CACHE_TTL_SECONDS", DEFAULT_TTL_SECONDS)))
+    }
+
+    /// Drops a player's timelines whenever the store gets a new row for them.
+    pub fn start(cache: Arc<Self>, hub: &Hub) {
+        let mut changes = hub.changes();
+
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => cache.invalidate(&change.player_uuid),
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(target: "hardcore-api", Level::WARN, "timeline cache fell behind, missed {} changes", missed);
+                        // any of the players could have changed.
+                        cache.clear();
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub fn invalidate(&self, player_uuid: &Uuid) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.write().retain(|(uuid, _), _| uuid != player_uuid);
+    }
+
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.write().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.read().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The same as `Timeline::build`.
+    pub async fn get(&self, store: &dyn Store, player: &Player, rules: &RuleSet, season: Option<&Season>) -> Result<Timeline, Error> {
+        let key = (player.player_uuid, season.map(|s| s.id));
+        if let Some(timeline) = self.lookup(&key, player, true) {
+            return Ok(timeline);
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let timeline = Timeline::build(store, player, rules, season).await?;
+        self.insert(key, player, &timeline, generation);
+        Ok(timeline)
+    }
+
+    /// Every player's timeline, the same as `Timeline::build_each`. Only the players that aren't cached are built, with a
+    /// query per player when there are only a few of them.
+    pub async fn get_all(&self, store: &dyn Store, rules: &RuleSet, season: Option<&Season>) -> Result<Vec<(Player, Timeline)>, Error> {
+        self.load_all(store, rules, season, true).await
+    }
+
+    /// The same as `get_all` without counting hits and misses, for reads that aren't requests
+    /// like the metrics gauges.
+    pub async fn get_all_uncounted(&self, store: &dyn Store, rules: &RuleSet, season: Option<&Season>) -> Result<Vec<(Player, Timeline)>, Error> {
+        self.load_all(store, rules, season, false).await
+    }
+
+    async fn load_all(&self, store: &dyn Store, rules: &RuleSet, season: Option<&Season>, counted: bool) -> Result<Vec<(Player, Timeline)>, Error> {
+        let season_id = season.map(|s| s.id);
+        let players: Vec<Player> = store.get_players().await?.into_iter()
+            .filter(|player| season.is_none_or(|s| s.includes(player)))
+            .collect();
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let mut timelines: Vec<Option<Timeline>> = players.iter()
+            .map(|player| self.lookup(&(player.player_uuid, season_id), player, counted))
+            .collect();
+        let missing = timelines.iter().filter(|t| t.is_none()).count();
+
+        if missing > BATCH_AFTER {
+            let mut deaths = store.get_deaths().await?;
+            let mut offenses = store.get_offenses().await?;
+            let mut extra_lives = store.get_extra_lives().await?;
+            for (player, timeline) in players.iter().zip(timelines.iter_mut()).filter(|(_, t)| t.is_none()) {
+                let built = Timeline::build_from_parts(
+                    player,
+                    deaths.remove(&player.player_uuid).unwrap_or_default(),
+                    offenses.remove(&player.player_uuid).unwrap_or_default(),
+                    extra_lives.remove(&player.player_uuid).unwrap_or_default(),
+                    rules,
+                    season,
+                );
+                self.insert((player.player_uuid, season_id), player, &built, generation);
+                *timeline = Some(built);
+            }
+        } else if missing > 0 {
+            for (player, timeline) in players.iter().zip(timelines.iter_mut()).filter(|(_, t)| t.is_none()) {
+                let built = Timeline::build(store, player, rules, season).await?;
+                self.insert((player.player_uuid, season_id), player, &built, generation);
+                *timeline = Some(built);
+            }
+        }
+
+        Ok(players.into_iter()
+            .zip(timelines)
+            .filter_map(|(player, timeline)| timeline.map(|timeline| (player, timeline)))
+            .collect())
+    }
+
+    /// A timeline that is still good for the player row, counted as a hit or a miss when `counted`.
+    fn lookup(&self, key: &Key, player: &Player, counted: bool) -> Option<Timeline> {
+        let found = self.read().get(key)
+            .filter(|entry| entry.built.elapsed() < self.ttl && same_player(&entry.player, player))
+            .map(|entry| entry.timeline.clone());
+
+        if counted {
+            match found {
+                Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+                None => self.misses.fetch_add(1, Ordering::Relaxed),
+            };
+        }
+        found
+    }
+
+    fn insert(&self, key: Key, player: &Player, timeline: &Timeline, generation: u64) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.write();
+        // checked with the lock held, so an invalidation can't happen in between.
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        entries.insert(key, Entry { player: player.clone(), timeline: timeline.clone(), built: Instant::now() });
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<Key, Entry>> {
+        // a panic while holding the lock can't leave the map half written, so ignore poisoning.
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<Key, Entry>> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Updating a player doesn't send a change, so the row itself is compared.
+fn same_player(a: &Player, b: &Player) -> bool {
+    a.name == b.name && a.joined == b.joined && a.playtime == b.playtime
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use crate::store::DynStore;
+    use crate::store::memory::{Fixture, MemoryStore};
+    use crate::test_support::steve;
+    use crate::types::death::NewDeath;
+    use super::*;
+
+    fn store() -> MemoryStore {
+        MemoryStore::new(Fixture { players: vec![steve()], ..Fixture::default() })
+    }
+
+    #[tokio::test]
+    async fn a_second_build_is_a_hit() {
+        let store = store();
+        let cache = TimelineCache::new(Duration::from_secs(60));
+
+        let first = cache.get_all(&store, &RuleSet::default(), None).await.unwrap();
+        let second = cache.get_all(&store, &RuleSet::default(), None).await.unwrap();
+
+        assert_eq!(first[0].1, second[0].1);
+        assert_eq!(cache.stats(), CacheStats { entries: 1, hits: 1, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn a_new_row_drops_the_players_timeline() {
+        let store = store();
+        let cache = TimelineCache::new(Duration::from_secs(60));
+        cache.get(&store, &steve(), &RuleSet::default(), None).await.unwrap();
+
+        let death = NewDeath { player_uuid: steve().player_uuid, stamp: Utc::now(), playtime: 36_000, reason: "fell".to_string(), imported: false };
+        store.add_death(&death).await.unwrap();
+        cache.invalidate(&steve().player_uuid);
+
+        let timeline = cache.get(&store, &steve(), &RuleSet::default(), None).await.unwrap();
+        assert_eq!(timeline.events[1].context, "fell");
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn changes_from_the_hub_drop_the_players_timeline() {
+        let store: DynStore = Arc::new(store());
+        let hub = Hub::start(store.clone(), RuleSet::default()).await.unwrap();
+        let cache = Arc::new(TimelineCache::new(Duration::from_secs(60)));
+        TimelineCache::start(cache.clone(), &hub);
+        cache.get(store.as_ref(), &steve(), &RuleSet::default(), None).await.unwrap();
+
+        let death = NewDeath { player_uuid: steve().player_uuid, stamp: Utc::now(), playtime: 36_000, reason: "fell".to_string(), imported: false };
+        store.add_death(&death).await.unwrap();
+        // the change is forwarded by the hub's task first.
+        for _ in 0..100 {
+            if cache.stats().entries == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn a_changed_player_row_is_a_miss() {
+        let store = store();
+        let cache = TimelineCache::new(Duration::from_secs(60));
+        cache.get(&store, &steve(), &RuleSet::default(), None).await.unwrap();
+
+        let moved_on = Player { playtime: 80_000, ..steve() };
+        cache.get(&store, &moved_on, &RuleSet::default(), None).await.unwrap();
+
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn a_ttl_of_zero_never_keeps_anything() {
+        let store = store();
+        let cache = TimelineCache::new(Duration::ZERO);
+
+        cache.get_all(&store, &RuleSet::default(), None).await.unwrap();
+        cache.get_all(&store, &RuleSet::default(), None).await.unwrap();
+
+        assert_eq!(cache.stats(), CacheStats { entries: 0, hits: 0, misses: 2 });
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::anomalies::{self, Severity};
+use crate::errors::Error;
+use crate::import::{self, ImportFormat, ImportKind};
+use crate::projection::Projector;
+use crate::rules::RuleSet;
+use crate::store::{DynStore, Store};
+use crate::store::memory::Fixture;
+use crate::timeline::Timeline;
+use crate::types::season::NewSeason;
+
+pub const USAGE: &str = "Usage: HardcoreApi [command]
+
+Commands:
+  serve                   run the api, the default
+  timeline <name|uuid>    print a player's timeline
+  export                  print every player, death, offense, extra life and season as a fixture
+  import [--dry-run] <file>
+                          add the rows of a fixture file that aren't already there
+  import [--dry-run] <players|deaths|offenses|extra_lives> <file.csv|file.ndjson>
+                          add the rows of a csv or ndjson file that aren't already there
+  check                   print every anomaly, exits with 1 when any of them is an error
+  migrate                 create or upgrade the tables
+  rebuild                 rebuild every player's timeline projection
+  help                    print this";
+
+/// What the binary was asked to do, the store and rules are set up the same way for every command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Timeline(String),
+    Export,
+    Import {
+        /// Empty for a fixture file.
+        kind: Option<ImportKind>,
+        path: String,
+        dry_run: bool,
+    },
+    Check,
+    Migrate,
+    Rebuild,
+    Help,
+}
+
+impl Command {
+    /// Parses the arguments after the binary name, no arguments is `serve`.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let dry_run = args.first() == Some(&"import") && args.contains(&"--dry-run");
+        if dry_run {
+            args.retain(|arg| *arg != "--dry-run");
+        }
+        match args[..] {
+            [] | ["serve"] => Ok(Command::Serve),
+            ["timeline", player] => Ok(Command::Timeline(player.to_string())),
+            ["export"] => Ok(Command::Export),
+            ["import", path] => Ok(Command::Import { kind: None, path: path.to_string(), dry_run }),
+            ["import", kind, path] => Ok(Command::Import { kind: Some(kind.parse()?), path: path.to_string(), dry_run }),
+            ["check"] => Ok(Command::Check),
+            ["migrate"] => Ok(Command::Migrate),
+            ["rebuild"] => Ok(Command::Rebuild),
+            ["help"] | ["--help"] | ["-h"] => Ok(Command::Help),
+            _ => Err(format!("unknown command '{}'", args.join(" "))),
+        }
+    }
+}
+
+/// Why a command stopped, the binary exits with 1 for either.
+#[derive(Debug)]
+pub enum Failure {
+    Error(Error),
+    /// The command worked but found something wrong, e.g. an anomaly that is an error.
+    Found(String),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Error(e)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // the internal detail is hidden from api clients, but not from the person at the shell.
+            Failure::Error(Error::Internal(detail)) => write!(f, "{}", detail),
+            Failure::Error(e) => write!(f, "{}", e),
+            Failure::Found(what) => write!(f, "{}", what),
+        }
+    }
+}
+
+/// Prints one player's timeline, looked up by uuid or by name.
+pub async fn timeline(store: &dyn Store, rules: &RuleSet, player: &str) -> Result<(), Failure> {
+    let found = match Uuid::parse_str(player) {
+        Ok(player_uuid) => store.get_player(&player_uuid).await?,
+        Err(_) => store.get_player_by_name(player).await?,
+    };
+    let player = match found {
+        Some(res) => res,
+        None => return Err(Error::NotFound("Player".to_string()).into()),
+    };
+
+    let timeline = Timeline::build(store, &player, rules, None).await?;
+    print_json(&timeline)
+}
+
+/// Prints every row as a fixture, it can be loaded by the in-memory store or imported.
+pub async fn export(store: &dyn Store) -> Result<(), Failure> {
+    let mut fixture = Fixture {
+        players: store.get_players().await?,
+        deaths: store.get_deaths().await?.into_values().flatten().collect(),
+        extra_lives: store.get_extra_lives().await?.into_values().flatten().collect(),
+        offenses: store.get_offenses().await?.into_values().flatten().collect(),
+        seasons: store.get_seasons().await?,
+    };
+    // the loaders group by player, put the rows back in the order they were added.
+    fixture.deaths.sort_by_key(|d| d.id);
+    fixture.extra_lives.sort_by_key(|e| e.id);
+    fixture.offenses.sort_by_key(|o| o.id);
+
+    print_json(&fixture)
+}
+
+/// Adds the rows of a fixture, CSV or NDJSON file that aren't already in the store and prints
+/// the report. It is a failure when any row couldn't be imported. The api doesn't project
+/// imported rows, so with PROJECTIONS on they are all rebuilt here once the rows are in.
+pub async fn import(store: DynStore, rules: &RuleSet, kind: Option<ImportKind>, path: &str, dry_run: bool) -> Result<(), Failure> {
+    let rows = match kind {
+        None => {
+            let fixture = Fixture::from_file(path)?;
+            import_seasons(store.as_ref(), &fixture, dry_run).await?;
+            import::fixture_rows(&fixture)
+        },
+        Some(kind) => {
+            let format = match ImportFormat::from_path(path) {
+                Some(res) => res,
+                None => return Err(Failure::Found(format!("{} isn't a .csv or .ndjson file", path))),
+            };
+            let data = match std::fs::read_to_string(path) {
+                Ok(res) => res,
+                Err(e) => return Err(Failure::Found(format!("couldn't read {}: {}", path, e))),
+            };
+            import::parse(kind, format, &data)?
+        },
+    };
+
+    let report = import::import(store.as_ref(), rows, dry_run).await?;
+    print_json(&report)?;
+
+    if let Some((projector, _)) = Projector::from_env(store, rules.clone()).filter(|_| !dry_run && report.imported > 0) {
+        let rebuilt = projector.rebuild_all().await?;
+        eprintln!("rebuilt the projections of {} players", rebuilt);
+    }
+
+    if !report.errors.is_empty() {
+        return Err(Failure::Found(format!("{} rows couldn't be imported", report.errors.len())));
+    }
+    Ok(())
+}
+
+/// Seasons aren't rows of an import, the ones in the fixture are added when there isn't a season
+/// with the same name.
+async fn import_seasons(store: &dyn Store, fixture: &Fixture, dry_run: bool) -> Result<(), Failure> {
+    for season in &fixture.seasons {
+        if dry_run || store.get_season(&season.name).await?.is_some() {
+            continue;
+        }
+        let new_season = NewSeason { name: season.name.clone(), starts: season.starts, ends: season.ends, rules: season.rules.clone() };
+        new_season.validate()?;
+        store.add_season(&new_season).await?;
+    }
+    Ok(())
+}
+
+/// Prints every anomaly, it is a failure when any of them is an error.
+pub async fn check(store: &dyn Store, rules: &RuleSet) -> Result<(), Failure> {
+    let anomalies = anomalies::check_all(store, rules).await?;
+    print_json(&anomalies)?;
+
+    let errors = anomalies.iter().filter(|a| a.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(Failure::Found(format!("found {} anomalies that are errors", errors)));
+    }
+    Ok(())
+}
+
+pub async fn migrate(store: &dyn Store) -> Result<(), Failure> {
+    store.run_migrations().await?;
+    eprintln!("the tables are up to date");
+    Ok(())
+}
+
+/// Rebuilds `player_timeline_stats` and `timeline_events` from the players' rows.
+pub async fn rebuild(store: DynStore, rules: &RuleSet) -> Result<(), Failure> {
+    let rebuilt = Projector::new(store, rules.clone()).rebuild_all().await?;
+    eprintln!("rebuilt the projections of {} players", rebuilt);
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Failure> {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{}", json);
+            Ok(())
+        },
+        Err(e) => Err(Error::Internal(format!("couldn't write json: {}", e)).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn no_command_is_serve() {
+        assert_eq!(parse(&[]), Ok(Command::Serve));
+        assert_eq!(parse(&["serve"]), Ok(Command::Serve));
+    }
+
+    #[test]
+    fn commands_take_their_arguments() {
+        assert_eq!(parse(&["timeline", "Steve"]), Ok(Command::Timeline("Steve".to_string())));
+        assert_eq!(parse(&["import", "rows.json"]), Ok(Command::Import { kind: None, path: "rows.json".to_string(), dry_run: false }));
+        assert_eq!(
+            parse(&["import", "--dry-run", "deaths", "deaths.csv"]),
+            Ok(Command::Import { kind: Some(ImportKind::Deaths), path: "deaths.csv".to_string(), dry_run: true }),
+        );
+        assert_eq!(parse(&["check"]), Ok(Command::Check));
+        assert_eq!(parse(&["rebuild"]), Ok(Command::Rebuild));
+    }
+
+    #[test]
+    fn missing_or_extra_arguments_are_an_error() {
+        assert!(parse(&["timeline"]).is_err());
+        assert!(parse(&["check", "now"]).is_err());
+        assert!(parse(&["destroy"]).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Reads an env variable, using the default when it is not set. A value that doesn't parse
+/// stops the api, a typo shouldn't quietly fall back to the default.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T
+where
+    T::Err: Display,
+{
+    env_parse(name).unwrap_or(default)
+}
+
+/// Reads an env variable that has no default, empty when it is not set.
+pub fn env_parse<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(v) => Some(v),
+            Err(e) => panic!("{} is invalid: {}", name, e),
+        },
+        Err(_) => None,
+    }
+}
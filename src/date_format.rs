@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc /*, NaiveDateTime*/};
 use serde::{self, /*Deserialize,*/ Serializer /*, Deserializer*/};
 
-const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+pub const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
 // The signature of a serialize_with function must follow the pattern:
 //
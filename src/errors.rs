@@ -1,58 +1,132 @@
 use std::fmt::Formatter;
+use serde::Serialize;
 use tracing::{event, instrument, Level};
+use uuid::Uuid;
 use warp::{Rejection, Reply};
 use warp::body::BodyDeserializeError;
 use warp::cors::CorsForbidden;
 use warp::http::StatusCode;
-use warp::reject::Reject;
-use crate::errors::Error::DatabaseQueryError;
+use warp::reject::{InvalidQuery, PayloadTooLarge, Reject, UnsupportedMediaType};
 
 #[derive(Debug)]
 pub enum Error {
-    DatabaseQueryError,
+    /// The thing that was not found, e.g. "Player".
+    NotFound(String),
+    InvalidUuid(String),
+    BadQueryParam(String),
+    InvalidInput(String),
+    Unauthorized,
+    Forbidden,
+    Conflict(String),
+    /// The detail is only logged, it is never sent to the client.
+    Internal(String),
 }
 
 impl Reject for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &*self {
-            Error::DatabaseQueryError => write!(f, "Database error"),
+        match self {
+            Error::NotFound(what) => write!(f, "{} not found", what),
+            Error::InvalidUuid(value) => write!(f, "'{}' is not a valid uuid", value),
+            Error::BadQueryParam(reason) => write!(f, "Bad query parameter: {}", reason),
+            Error::InvalidInput(reason) => write!(f, "Invalid input: {}", reason),
+            Error::Unauthorized => write!(f, "Unauthorized"),
+            Error::Forbidden => write!(f, "The api key is missing the scope for this request"),
+            Error::Conflict(reason) => write!(f, "Conflict: {}", reason),
+            Error::Internal(_) => write!(f, "Internal server error"),
         }
     }
 }
 
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::InvalidUuid(_) => "invalid_uuid",
+            Error::BadQueryParam(_) => "bad_query_param",
+            Error::InvalidInput(_) => "invalid_input",
+            Error::Unauthorized => "unauthorized",
+            Error::Forbidden => "forbidden",
+            Error::Conflict(_) => "conflict",
+            Error::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::InvalidUuid(_) => StatusCode::BAD_REQUEST,
+            Error::BadQueryParam(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        // 23505 is a unique_violation, the row already exists.
+        if let Some(db_error) = e.as_database_error() {
+            if db_error.code().as_deref() == Some("23505") {
+                return Error::Conflict(db_error.message().to_string());
+            }
+        }
+        Error::Internal(format!("database error: {}", e))
+    }
+}
+
+/// The body of every error response.
+#[derive(Serialize, Debug)]
+pub struct ErrorResponse {
+    pub code: &'static str,
+    pub message: String,
+    /// Also logged with the error so a response can be matched to the logs.
+    pub request_id: String,
+}
+
+fn reply(code: &'static str, message: String, status: StatusCode, request_id: Uuid) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&ErrorResponse {
+            code,
+            message,
+            request_id: request_id.to_string(),
+        }),
+        status,
+    )
+}
+
 #[instrument]
 pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
-    if let Some(Error::DatabaseQueryError) = r.find() {
-        event!(Level::ERROR, "Database query error");
-        Ok(warp::reply::with_status(
-            DatabaseQueryError.to_string(),
-            StatusCode::UNPROCESSABLE_ENTITY,
-        ))
+    let request_id = Uuid::new_v4();
+
+    if let Some(error) = r.find::<Error>() {
+        match error {
+            Error::Internal(detail) => event!(target: "hardcore-api", Level::ERROR, %request_id, "{}", detail),
+            _ => event!(target: "hardcore-api", Level::WARN, %request_id, "{}", error),
+        }
+        Ok(reply(error.code(), error.to_string(), error.status(), request_id))
     } else if let Some(error) = r.find::<CorsForbidden>() {
-        event!(Level::ERROR, "cors forbidden error: {}", error);
-        Ok(warp::reply::with_status(
-            error.to_string(),
-            StatusCode::FORBIDDEN,
-        ))
+        event!(target: "hardcore-api", Level::ERROR, %request_id, "cors forbidden error: {}", error);
+        Ok(reply("forbidden", error.to_string(), StatusCode::FORBIDDEN, request_id))
     } else if let Some(error) = r.find::<BodyDeserializeError>() {
-        event!(Level::ERROR, "cannot deserialize request body: {}", error);
-        Ok(warp::reply::with_status(
-            error.to_string(),
-            StatusCode::UNPROCESSABLE_ENTITY,
-        ))
-    } else if let Some(error) = r.find::<Error>() {
-        event!(Level::ERROR, "{}", error);
-        Ok(warp::reply::with_status(
-            error.to_string(),
-            StatusCode::UNPROCESSABLE_ENTITY,
-        ))
+        event!(target: "hardcore-api", Level::WARN, %request_id, "cannot deserialize request body: {}", error);
+        Ok(reply("invalid_input", error.to_string(), StatusCode::BAD_REQUEST, request_id))
+    } else if let Some(error) = r.find::<InvalidQuery>() {
+        let error = Error::BadQueryParam(error.to_string());
+        event!(target: "hardcore-api", Level::WARN, %request_id, "{}", error);
+        Ok(reply(error.code(), error.to_string(), error.status(), request_id))
+    } else if let Some(error) = r.find::<PayloadTooLarge>() {
+        event!(target: "hardcore-api", Level::WARN, %request_id, "{}", error);
+        Ok(reply("payload_too_large", error.to_string(), StatusCode::PAYLOAD_TOO_LARGE, request_id))
+    } else if let Some(error) = r.find::<UnsupportedMediaType>() {
+        event!(target: "hardcore-api", Level::WARN, %request_id, "{}", error);
+        Ok(reply("unsupported_media_type", error.to_string(), StatusCode::UNSUPPORTED_MEDIA_TYPE, request_id))
     } else {
-        event!(Level::WARN, "Requested route was not found");
-        Ok(warp::reply::with_status(
-            "Route not found".to_string(),
-            StatusCode::NOT_FOUND,
-        ))
+        event!(target: "hardcore-api", Level::WARN, %request_id, "Requested route was not found");
+        Ok(reply("not_found", "Route not found".to_string(), StatusCode::NOT_FOUND, request_id))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::date_format;
+use crate::leaderboard::LeaderboardRow;
+use crate::timeline::{Timeline, TimelineEvent};
+use crate::types::player::Player;
+
+/// The columns of `events.csv`, in the order they're written.
+pub const EVENT_COLUMNS: &[&str] = &[
+    "player_uuid", "player_name", "id", "stamp", "what", "context", "playtime", "span", "unranked", "no_lives",
+    "ranked_lives_after", "unranked_lives_after",
+];
+
+/// The columns of `timelines.csv`, the same as a leaderboard row.
+pub const TIMELINE_COLUMNS: &[&str] = &[
+    "rank", "player_uuid", "player_name", "player_state", "survived_seconds", "longest_life_seconds",
+    "shortest_life_seconds", "deaths", "offenses",
+];
+
+/// A timeline event with the player it belongs to, one line of `events.ndjson`.
+#[derive(Serialize, Debug)]
+pub struct ExportEvent<'a> {
+    pub player_uuid: Uuid,
+    pub player_name: &'a str,
+    #[serde(flatten)]
+    pub event: &'a TimelineEvent,
+}
+
+pub fn csv_header(columns: &[&str]) -> String {
+    csv_line(columns)
+}
+
+/// Every event of the player's timeline as CSV lines.
+pub fn events_csv(player: &Player, timeline: &Timeline) -> String {
+    let mut lines = String::new();
+    for event in &timeline.events {
+        lines.push_str(&csv_line(&[
+            &player.player_uuid as &dyn Display,
+            &timeline.player_name,
+            &event.id.map(|id| id.to_string()).unwrap_or_default(),
+            // the same format as the json.
+            &event.stamp.format(date_format::FORMAT),
+            &event.what,
+            &event.context,
+            &event.playtime,
+            &event.span,
+            &event.unranked,
+            &event.no_lives,
+            &event.lives_after.ranked,
+            &event.lives_after.unranked,
+        ]));
+    }
+    lines
+}
+
+/// Every event of the player's timeline as JSON lines.
+pub fn events_ndjson(player: &Player, timeline: &Timeline) -> String {
+    let mut lines = String::new();
+    for event in &timeline.events {
+        let export_event = ExportEvent { player_uuid: player.player_uuid, player_name: &timeline.player_name, event };
+        lines.push_str(&json_line(&export_event));
+    }
+    lines
+}
+
+pub fn row_csv(row: &LeaderboardRow) -> String {
+    csv_line(&[
+        &row.rank as &dyn Display,
+        &row.player_uuid,
+        &row.player_name,
+        &format!("{:?}", row.player_state),
+        &row.survived_seconds,
+        &row.longest_life_seconds,
+        &row.shortest_life_seconds,
+        &row.deaths,
+        &row.offenses,
+    ])
+}
+
+pub fn row_ndjson(row: &LeaderboardRow) -> String {
+    json_line(row)
+}
+
+/// Fields with a comma, quote or line break are quoted, quotes in them are doubled (RFC 4180).
+fn csv_line<T: Display + ?Sized>(fields: &[&T]) -> String {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| {
+            let field = field.to_string();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn json_line<T: Serialize>(value: &T) -> String {
+    // the exported types are plain structs, serializing them can't fail.
+    match serde_json::to_string(value) {
+        Ok(json) => format!("{}\n", json),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use crate::rules::RuleSet;
+    use crate::test_support::steve;
+    use crate::types::death::Death;
+    use super::*;
+
+    fn timeline(reason: &str) -> Timeline {
+        let deaths = vec![Death {
+            id: 4,
+            player_uuid: steve().player_uuid,
+            stamp: Utc.timestamp_opt(1_725_003_600, 0).unwrap(),
+            playtime: 36_000,
+            reason: reason.to_string(),
+        }];
+        Timeline::build_from_parts(&steve(), deaths, vec![], vec![], &RuleSet::default(), None)
+    }
+
+    #[test]
+    fn every_event_is_a_csv_line() {
+        let csv = events_csv(&steve(), &timeline("fell"));
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "00000000-0000-0000-0000-000000000001,Steve,,2024-08-30 06:40:00,Joined,Joined Hardcore,0,0,false,false,3,0");
+        assert_eq!(lines[1], "00000000-0000-0000-0000-000000000001,Steve,4,2024-08-30 07:40:00,Died,fell,1800,1800,false,false,2,0");
+        assert_eq!(csv_header(EVENT_COLUMNS).trim_end().split(',').count(), lines[1].split(',').count());
+    }
+
+    #[test]
+    fn csv_fields_with_commas_and_quotes_are_quoted() {
+        let csv = events_csv(&steve(), &timeline("hit by \"Notch\", twice"));
+
+        assert!(csv.lines().nth(1).unwrap().contains(",Died,\"hit by \"\"Notch\"\", twice\",1800,"));
+    }
+
+    #[test]
+    fn ndjson_events_have_the_player_and_the_event_fields() {
+        let ndjson = events_ndjson(&steve(), &timeline("fell"));
+        let died: serde_json::Value = serde_json::from_str(ndjson.lines().nth(1).unwrap()).unwrap();
+
+        assert_eq!(died["player_name"], "Steve");
+        assert_eq!(died["what"], "Died");
+        assert_eq!(died["span"], 1800);
+        assert_eq!(died["lives_after"]["ranked"], 2);
+    }
+}
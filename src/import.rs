@@ -0,0 +1,425 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+use crate::errors::Error;
+use crate::store::Store;
+use crate::store::memory::Fixture;
+use crate::types::death::NewDeath;
+use crate::types::extra_life::NewExtraLife;
+use crate::types::offense::NewOffense;
+use crate::types::player::{NewPlayer, PlayerUpdate};
+
+/// CSV columns that are numbers, everything else is read as text.
+const NUMBER_COLUMNS: &[&str] = &["playtime"];
+
+/// What the rows of an import are, a file only has one kind of row.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportKind {
+    Players,
+    Deaths,
+    Offenses,
+    ExtraLives,
+}
+
+impl FromStr for ImportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "players" => Ok(ImportKind::Players),
+            "deaths" => Ok(ImportKind::Deaths),
+            "offenses" => Ok(ImportKind::Offenses),
+            "extra_lives" | "extra-lives" => Ok(ImportKind::ExtraLives),
+            _ => Err(format!("unknown kind of row '{}'", s)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// The first line is the column names, the same names as the JSON fields.
+    Csv,
+    /// A JSON object on each line.
+    Ndjson,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.ends_with(".csv") {
+            Some(ImportFormat::Csv)
+        } else if path.ends_with(".ndjson") || path.ends_with(".jsonl") {
+            Some(ImportFormat::Ndjson)
+        } else {
+            None
+        }
+    }
+}
+
+/// A row that was read, checked the same way as the write endpoints check them.
+#[derive(Debug, Clone)]
+pub enum ImportRow {
+    Player(NewPlayer),
+    Death(NewDeath),
+    Offense(NewOffense),
+    ExtraLife(NewExtraLife),
+}
+
+impl ImportRow {
+    fn kind(&self) -> ImportKind {
+        match self {
+            ImportRow::Player(_) => ImportKind::Players,
+            ImportRow::Death(_) => ImportKind::Deaths,
+            ImportRow::Offense(_) => ImportKind::Offenses,
+            ImportRow::ExtraLife(_) => ImportKind::ExtraLives,
+        }
+    }
+
+    fn player_uuid(&self) -> Uuid {
+        match self {
+            ImportRow::Player(p) => p.player_uuid,
+            ImportRow::Death(d) => d.player_uuid,
+            ImportRow::Offense(o) => o.player_uuid,
+            ImportRow::ExtraLife(e) => e.player_uuid,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            ImportRow::Player(p) => p.update().validate(),
+            ImportRow::Death(d) => d.validate(),
+            ImportRow::Offense(o) => o.validate(),
+            ImportRow::ExtraLife(e) => e.validate(),
+        }
+    }
+
+    /// Rows with the same key are the same row. Players don't have one, they are updated instead.
+    fn key(&self) -> Option<(ImportKind, Uuid, DateTime<Utc>)> {
+        match self {
+            ImportRow::Player(_) => None,
+            ImportRow::Death(d) => Some((ImportKind::Deaths, d.player_uuid, d.stamp)),
+            ImportRow::Offense(o) => Some((ImportKind::Offenses, o.player_uuid, o.stamp)),
+            ImportRow::ExtraLife(e) => Some((ImportKind::ExtraLives, e.player_uuid, e.stamp)),
+        }
+    }
+
+    fn from_value(kind: ImportKind, value: Value) -> Result<Self, String> {
+        let row = match kind {
+            ImportKind::Players => serde_json::from_value(value).map(ImportRow::Player),
+            ImportKind::Deaths => serde_json::from_value(value).map(ImportRow::Death),
+            ImportKind::Offenses => serde_json::from_value(value).map(ImportRow::Offense),
+            ImportKind::ExtraLives => serde_json::from_value(value).map(ImportRow::ExtraLife),
+        };
+        row.map_err(|e| e.to_string())
+    }
+}
+
+/// A row from the file with its row number, or why it couldn't be read.
+pub type ParsedRow = (usize, Result<ImportRow, String>);
+
+/// A row that wasn't imported, rows are numbered from 1 and the CSV header is row 1.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Nothing was written, the counts are what would have been imported.
+    pub dry_run: bool,
+    pub rows: usize,
+    pub imported: usize,
+    /// Rows that were already in the store or earlier in the file.
+    pub duplicates: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads every row of the data, a row that can't be read is an error on that row.
+pub fn parse(kind: ImportKind, format: ImportFormat, data: &str) -> Result<Vec<ParsedRow>, Error> {
+    match format {
+        ImportFormat::Ndjson => Ok(data.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let row = serde_json::from_str(line)
+                    .map_err(|e| e.to_string())
+                    .and_then(|value| ImportRow::from_value(kind, value));
+                (i + 1, row)
+            })
+            .collect()),
+        ImportFormat::Csv => {
+            let mut records = csv_records(data)?.into_iter();
+            let header = match records.next() {
+                Some((_, header)) => header,
+                None => return Ok(vec![]),
+            };
+            Ok(records
+                .map(|(row, fields)| (row, csv_object(&header, fields).and_then(|value| ImportRow::from_value(kind, value))))
+                .collect())
+        },
+    }
+}
+
+/// Every row of a fixture, players first so the rest of the rows can use them. Rows are
+/// numbered in that order.
+pub fn fixture_rows(fixture: &Fixture) -> Vec<ParsedRow> {
+    let players = fixture.players.iter().map(|p| ImportRow::Player(NewPlayer {
+        player_uuid: p.player_uuid, name: p.name.clone(), joined: p.joined, playtime: p.playtime,
+    }));
+    let deaths = fixture.deaths.iter().map(|d| ImportRow::Death(NewDeath {
+        player_uuid: d.player_uuid, stamp: d.stamp, playtime: d.playtime, reason: d.reason.clone(), imported: false,
+    }));
+    let offenses = fixture.offenses.iter().map(|o| ImportRow::Offense(NewOffense {
+        player_uuid: o.player_uuid, stamp: o.stamp, reason: o.reason.clone(), playtime: o.playtime, imported: false,
+    }));
+    let extra_lives = fixture.extra_lives.iter().map(|e| ImportRow::ExtraLife(NewExtraLife {
+        player_uuid: e.player_uuid, stamp: e.stamp, reason: e.reason.clone(), playtime: e.playtime, imported: false,
+    }));
+
+    players.chain(deaths).chain(offenses).chain(extra_lives)
+        .enumerate()
+        .map(|(i, row)| (i + 1, Ok(row)))
+        .collect()
+}
+
+/// Adds the rows that are valid and not already in the store, the rest are reported. A dry run
+/// checks every row the same way but doesn't write anything.
+pub async fn import(store: &dyn Store, rows: Vec<ParsedRow>, dry_run: bool) -> Result<ImportReport, Error> {
+    let mut players: HashSet<Uuid> = store.get_players().await?.iter().map(|p| p.player_uuid).collect();
+    let mut seen = existing_keys(store, &rows).await?;
+
+    let mut report = ImportReport { dry_run, rows: rows.len(), imported: 0, duplicates: 0, errors: vec![] };
+    for (number, row) in rows {
+        let row = match row {
+            Ok(res) => res,
+            Err(message) => {
+                report.errors.push(RowError { row: number, message });
+                continue;
+            },
+        };
+        if let Err(e) = row.validate() {
+            report.errors.push(RowError { row: number, message: e.to_string() });
+            continue;
+        }
+        if row.kind() != ImportKind::Players && !players.contains(&row.player_uuid()) {
+            report.errors.push(RowError { row: number, message: format!("player {} not found", row.player_uuid()) });
+            continue;
+        }
+        if let Some(key) = row.key() {
+            if !seen.insert(key) {
+                report.duplicates += 1;
+                continue;
+            }
+        }
+
+        players.insert(row.player_uuid());
+        if !dry_run {
+            match write(store, &row).await {
+                Ok(()) => {},
+                // the store is down, the rest of the rows would fail the same way.
+                Err(e @ Error::Internal(_)) => return Err(e),
+                Err(e) => {
+                    report.errors.push(RowError { row: number, message: e.to_string() });
+                    continue;
+                },
+            }
+        }
+        report.imported += 1;
+    }
+
+    Ok(report)
+}
+
+/// The rows are marked as imported, so a big import doesn't flood the live feed and webhooks.
+async fn write(store: &dyn Store, row: &ImportRow) -> Result<(), Error> {
+    match row {
+        ImportRow::Player(p) => store.upsert_player(&p.player_uuid, &PlayerUpdate { imported: true, ..p.update() }).await.map(|_| ()),
+        ImportRow::Death(d) => store.add_death(&NewDeath { imported: true, ..d.clone() }).await.map(|_| ()),
+        ImportRow::Offense(o) => store.add_offense(&NewOffense { imported: true, ..o.clone() }).await.map(|_| ()),
+        ImportRow::ExtraLife(e) => store.add_extra_life(&NewExtraLife { imported: true, ..e.clone() }).await.map(|_| ()),
+    }
+}
+
+/// The keys of the rows already in the store, only for the kinds of row being imported.
+async fn existing_keys(store: &dyn Store, rows: &[ParsedRow]) -> Result<HashSet<(ImportKind, Uuid, DateTime<Utc>)>, Error> {
+    let kinds: HashSet<ImportKind> = rows.iter()
+        .filter_map(|(_, row)| row.as_ref().ok().map(ImportRow::kind))
+        .collect();
+
+    let mut keys = HashSet::new();
+    if kinds.contains(&ImportKind::Deaths) {
+        keys.extend(store.get_deaths().await?.into_values().flatten().map(|d| (ImportKind::Deaths, d.player_uuid, d.stamp)));
+    }
+    if kinds.contains(&ImportKind::Offenses) {
+        keys.extend(store.get_offenses().await?.into_values().flatten().map(|o| (ImportKind::Offenses, o.player_uuid, o.stamp)));
+    }
+    if kinds.contains(&ImportKind::ExtraLives) {
+        keys.extend(store.get_extra_lives().await?.into_values().flatten().map(|e| (ImportKind::ExtraLives, e.player_uuid, e.stamp)));
+    }
+    Ok(keys)
+}
+
+/// Turns a CSV row into the same JSON object the NDJSON would have.
+fn csv_object(header: &[String], fields: Vec<String>) -> Result<Value, String> {
+    if fields.len() != header.len() {
+        return Err(format!("the row has {} fields but the header has {}", fields.len(), header.len()));
+    }
+
+    let mut object = Map::new();
+    for (column, field) in header.iter().zip(fields) {
+        let value = match field.parse::<i64>() {
+            Ok(number) if NUMBER_COLUMNS.contains(&column.as_str()) => Value::from(number),
+            _ => Value::String(field),
+        };
+        object.insert(column.clone(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Splits CSV into numbered rows of fields, blank rows are skipped. Quoted fields can have
+/// commas, doubled quotes and line breaks in them (RFC 4180).
+fn csv_records(data: &str) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                },
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {},
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            },
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::InvalidInput("the csv has a quoted field that is never closed".to_string()));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records.into_iter()
+        .enumerate()
+        .map(|(i, record)| (i + 1, record.into_iter().map(|f| f.trim().to_string()).collect::<Vec<_>>()))
+        .filter(|(_, record)| !(record.len() == 1 && record[0].is_empty()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use crate::store::memory::MemoryStore;
+    use crate::test_support::steve;
+    use crate::types::death::Death;
+    use super::*;
+
+    fn store() -> MemoryStore {
+        let death = Death {
+            id: 1,
+            player_uuid: steve().player_uuid,
+            stamp: Utc.timestamp_opt(1_725_003_600, 0).unwrap(),
+            playtime: 36_000,
+            reason: "fell".to_string(),
+        };
+        MemoryStore::new(Fixture { players: vec![steve()], deaths: vec![death], ..Fixture::default() })
+    }
+
+    const DEATHS: &str = "player_uuid,stamp,playtime,reason
+00000000-0000-0000-0000-000000000001,1725003600,36000,fell
+00000000-0000-0000-0000-000000000001,2024-08-30T08:40:00Z,54000,\"hit by \"\"Notch\"\", twice\"
+
+00000000-0000-0000-0000-000000000001,1725007200,not a number,drowned
+00000000-0000-0000-0000-000000000002,1725007200,54000,drowned
+00000000-0000-0000-0000-000000000001,2024-08-30T08:40:00Z,54000,the same stamp
+";
+
+    #[test]
+    fn csv_rows_are_numbered_and_quoted_fields_are_read() {
+        let rows = parse(ImportKind::Deaths, ImportFormat::Csv, DEATHS).unwrap();
+
+        assert_eq!(rows.iter().map(|(row, _)| *row).collect::<Vec<_>>(), vec![2, 3, 5, 6, 7]);
+        match &rows[1].1 {
+            Ok(ImportRow::Death(death)) => assert_eq!(death.reason, "hit by \"Notch\", twice"),
+            other => panic!("{:?}", other),
+        }
+        assert!(rows[2].1.as_ref().unwrap_err().contains("expected i32"));
+    }
+
+    #[tokio::test]
+    async fn duplicates_and_bad_rows_are_reported() {
+        let store = store();
+        let rows = parse(ImportKind::Deaths, ImportFormat::Csv, DEATHS).unwrap();
+
+        let report = import(&store, rows, false).await.unwrap();
+
+        assert_eq!(report.rows, 5);
+        assert_eq!(report.imported, 1);
+        // the first row is already in the store and the last is the same as the second.
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.errors.iter().map(|e| e.row).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(report.errors[1].message, "player 00000000-0000-0000-0000-000000000002 not found");
+        assert_eq!(store.get_player_deaths(&steve().player_uuid).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_dry_run_writes_nothing() {
+        let store = store();
+        let rows = parse(ImportKind::Deaths, ImportFormat::Csv, DEATHS).unwrap();
+
+        let report = import(&store, rows, true).await.unwrap();
+
+        assert!(report.dry_run);
+        assert_eq!(report.imported, 1);
+        assert_eq!(store.get_player_deaths(&steve().player_uuid).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn events_can_use_players_earlier_in_the_import() {
+        let store = store();
+        let alex = "{\"player_uuid\": \"00000000-0000-0000-0000-000000000002\", \"name\": \"Alex\", \"joined\": 1725000000, \"playtime\": 0}";
+        let death = "{\"player_uuid\": \"00000000-0000-0000-0000-000000000002\", \"stamp\": 1725003600, \"playtime\": 0, \"reason\": \"fell\"}";
+        let mut rows = parse(ImportKind::Players, ImportFormat::Ndjson, alex).unwrap();
+        rows.extend(parse(ImportKind::Deaths, ImportFormat::Ndjson, death).unwrap());
+
+        let report = import(&store, rows, false).await.unwrap();
+
+        assert_eq!(report.errors, vec![]);
+        assert_eq!(report.imported, 2);
+    }
+
+    #[tokio::test]
+    async fn imported_rows_are_marked_in_their_changes() {
+        let store = store();
+        let mut changes = store.watch().await.unwrap();
+        let rows = parse(ImportKind::Deaths, ImportFormat::Csv, DEATHS).unwrap();
+
+        import(&store, rows, false).await.unwrap();
+
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.player_uuid, steve().player_uuid);
+        assert!(change.imported);
+    }
+}
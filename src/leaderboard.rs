@@ -0,0 +1,251 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::timeline::{EventType, PlayerState, Timeline};
+use crate::types::player::Player;
+
+/// A player's stats without their events.
+#[derive(Serialize, Debug, Clone)]
+pub struct LeaderboardRow {
+    /// Position in the whole sorted leaderboard, starting at 1.
+    pub rank: usize,
+    pub player_uuid: Uuid,
+    pub player_name: String,
+    pub player_state: PlayerState,
+    pub survived_seconds: i32,
+    pub longest_life_seconds: i32,
+    pub shortest_life_seconds: i32,
+    pub deaths: usize,
+    pub offenses: usize,
+}
+
+impl LeaderboardRow {
+    pub fn from_timeline(player: &Player, timeline: &Timeline) -> Self {
+        LeaderboardRow {
+            rank: 0,
+            player_uuid: player.player_uuid,
+            player_name: timeline.player_name.clone(),
+            player_state: timeline.player_state,
+            survived_seconds: timeline.survived_seconds,
+            longest_life_seconds: timeline.longest_life_seconds,
+            shortest_life_seconds: timeline.shortest_life_seconds,
+            deaths: timeline.events.iter().filter(|e| e.what == EventType::Died).count(),
+            offenses: timeline.events.iter().filter(|e| e.what == EventType::Offense).count(),
+        }
+    }
+}
+
+/// The sort key of the last row of a page and its rank, the next page starts after it. Rows
+/// added or moved above it don't shift the next page, the ranks after it carry on from it.
+/// The value only means something under the sort and order it was made with, so it has them too.
+///
+/// Written as `sort:order:rank:value:name:uuid`, player names can't have a colon in them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub sort: SortBy,
+    pub order: SortOrder,
+    pub rank: usize,
+    pub value: i64,
+    /// Lowercase, the same as names are compared.
+    pub name: String,
+    pub player_uuid: Uuid,
+}
+
+impl Cursor {
+    pub fn after(row: &LeaderboardRow, sort: SortBy, order: SortOrder) -> Self {
+        Cursor {
+            sort,
+            order,
+            rank: row.rank,
+            value: sort_value(row, sort),
+            name: row.player_name.to_lowercase(),
+            player_uuid: row.player_uuid,
+        }
+    }
+
+    /// Whether the row is on a later page than the cursor.
+    pub fn is_before(&self, row: &LeaderboardRow) -> bool {
+        let by = sort_value(row, self.sort).cmp(&self.value);
+        let by = if self.order == SortOrder::Desc { by.reverse() } else { by };
+        by.then_with(|| row.player_name.to_lowercase().cmp(&self.name))
+            .then_with(|| row.player_uuid.cmp(&self.player_uuid))
+            == Ordering::Greater
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}:{}:{}", self.sort, self.order, self.rank, self.value, self.name, self.player_uuid)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a leaderboard cursor", s);
+        let parts: Vec<&str> = s.split(':').collect();
+        let [sort, order, rank, value, name, player_uuid] = parts[..] else {
+            return Err(invalid());
+        };
+        Ok(Cursor {
+            sort: sort.parse().map_err(|_| invalid())?,
+            order: order.parse().map_err(|_| invalid())?,
+            rank: rank.parse().map_err(|_| invalid())?,
+            value: value.parse().map_err(|_| invalid())?,
+            name: name.to_string(),
+            player_uuid: Uuid::parse_str(player_uuid).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Survived,
+    LongestLife,
+    ShortestLife,
+    Deaths,
+    Offenses,
+}
+
+impl SortBy {
+    /// The same as the query parameter.
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Survived => "survived",
+            SortBy::LongestLife => "longest_life",
+            SortBy::ShortestLife => "shortest_life",
+            SortBy::Deaths => "deaths",
+            SortBy::Offenses => "offenses",
+        }
+    }
+}
+
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [SortBy::Survived, SortBy::LongestLife, SortBy::ShortestLife, SortBy::Deaths, SortBy::Offenses]
+            .into_iter()
+            .find(|sort| sort.as_str() == s)
+            .ok_or_else(|| format!("'{}' is not a leaderboard sort", s))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortOrder::Asc => f.write_str("asc"),
+            SortOrder::Desc => f.write_str("desc"),
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            _ => Err(format!("'{}' is not a leaderboard order", s)),
+        }
+    }
+}
+
+/// One page of the leaderboard, sorted and filtered before the limit.
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    pub sort: SortBy,
+    pub order: SortOrder,
+    pub state: Option<StateFilter>,
+    /// The page starts after this row.
+    pub after: Option<Cursor>,
+    pub limit: usize,
+}
+
+/// The rows of the page, for rows that are all in memory. They aren't ranked yet, see `rank`.
+pub fn page(rows: Vec<LeaderboardRow>, query: &PageQuery) -> Vec<LeaderboardRow> {
+    let mut rows: Vec<LeaderboardRow> = rows.into_iter()
+        .filter(|row| query.state.is_none_or(|state| state.matches(row.player_state)))
+        .collect();
+    sort(&mut rows, query.sort, query.order);
+
+    let start = match &query.after {
+        Some(cursor) => rows.iter().position(|row| cursor.is_before(row)).unwrap_or(rows.len()),
+        None => 0,
+    };
+    let end = (start + query.limit).min(rows.len());
+    rows.drain(start..end).collect()
+}
+
+/// Sorts the rows, players with the same value are ordered by name so a page never changes
+/// order between requests.
+pub fn sort(rows: &mut [LeaderboardRow], sort: SortBy, order: SortOrder) {
+    rows.sort_by(|a, b| {
+        let by = sort_value(a, sort).cmp(&sort_value(b, sort));
+        let by = if order == SortOrder::Desc { by.reverse() } else { by };
+        by.then_with(|| compare_names(a, b))
+    });
+}
+
+/// Sets the ranks of a sorted page, the only place ranks are set. They carry on from the
+/// cursor's, so rows that moved above it don't shift them.
+pub fn rank(rows: &mut [LeaderboardRow], after: Option<&Cursor>) {
+    let first_rank = after.map_or(0, |cursor| cursor.rank);
+    for (i, row) in rows.iter_mut().enumerate() {
+        row.rank = first_rank + i + 1;
+    }
+}
+
+fn sort_value(row: &LeaderboardRow, sort: SortBy) -> i64 {
+    match sort {
+        SortBy::Survived => row.survived_seconds as i64,
+        SortBy::LongestLife => row.longest_life_seconds as i64,
+        SortBy::ShortestLife => row.shortest_life_seconds as i64,
+        SortBy::Deaths => row.deaths as i64,
+        SortBy::Offenses => row.offenses as i64,
+    }
+}
+
+fn compare_names(a: &LeaderboardRow, b: &LeaderboardRow) -> Ordering {
+    a.player_name.to_lowercase().cmp(&b.player_name.to_lowercase())
+        .then_with(|| a.player_uuid.cmp(&b.player_uuid))
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StateFilter {
+    Alive,
+    /// Every player that is out of lives, ghosts included.
+    Dead,
+    Ghost,
+}
+
+impl StateFilter {
+    pub fn matches(&self, state: PlayerState) -> bool {
+        match self {
+            StateFilter::Alive => state == PlayerState::Alive,
+            StateFilter::Dead => state != PlayerState::Alive,
+            StateFilter::Ghost => state == PlayerState::Ghost,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{event, Level};
+use uuid::Uuid;
+use crate::errors::Error;
+use crate::rules::RuleSet;
+use crate::store::{Change, ChangeKind, DynStore, Store, CHANGE_BUFFER};
+use crate::timeline::{EventType, PlayerState, Timeline, TimelineEvent};
+
+/// How many events a slow subscriber can fall behind before it starts missing them.
+const EVENT_BUFFER: usize = 256;
+
+/// A new event on a player's timeline, along with the player's state after it.
+#[derive(Serialize, Debug, Clone)]
+pub struct LiveEvent {
+    pub player_uuid: Uuid,
+    pub player_name: String,
+    pub player_state: PlayerState,
+    /// The player's survived time with this event included.
+    pub survived_seconds: i32,
+    /// Ranked and unranked lives the player has left.
+    pub lives_remaining: i32,
+    pub event: TimelineEvent,
+}
+
+/// Turns changes in the store into live events and sends them to every subscriber.
+///
+/// The hub is the only thing watching the store, each watch holds a database connection for
+/// good, so everything else that wants the changes gets them from `changes`.
+#[derive(Clone, Debug)]
+pub struct Hub {
+    sender: broadcast::Sender<LiveEvent>,
+    changes: broadcast::Sender<Change>,
+}
+
+impl Hub {
+    pub async fn start(store: DynStore, rules: RuleSet) -> Result<Self, Error> {
+        let mut store_changes = store.watch().await?;
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        let (changes_sender, _) = broadcast::channel(CHANGE_BUFFER);
+        let hub = Hub { sender: sender.clone(), changes: changes_sender.clone() };
+        // subscribed before the changes are forwarded, so the live feed doesn't miss the first ones.
+        let mut changes = changes_sender.subscribe();
+
+        // only forwards, so it keeps up with the store while the live events are built.
+        tokio::spawn(async move {
+            loop {
+                match store_changes.recv().await {
+                    // an error only means there are no subscribers.
+                    Ok(change) => { let _ = changes_sender.send(change); },
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(target: "hardcore-api", Level::WARN, "change feed fell behind, missed {} changes", missed);
+                    },
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            loop {
+                let change = match changes.recv().await {
+                    Ok(res) => res,
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(target: "hardcore-api", Level::WARN, "live feed fell behind, missed {} changes", missed);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+                // an import's rows aren't news, a backfill would flood the feed and webhooks.
+                if change.imported {
+                    continue;
+                }
+
+                match Self::to_live_event(store.as_ref(), &rules, &change).await {
+                    // an error only means there are no subscribers.
+                    Ok(Some(live_event)) => { let _ = sender.send(live_event); },
+                    Ok(None) => {},
+                    Err(e) => event!(target: "hardcore-api", Level::ERROR, "couldn't build live event for {:?}: {:?}", change, e),
+                }
+            }
+        });
+
+        Ok(hub)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Every change the store sends, before it is turned into a live event. Imported rows are
+    /// here too, they only aren't live events.
+    pub fn changes(&self) -> broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    /// Rebuilds the player's timeline and picks out the event for the new row.
+    async fn to_live_event(store: &dyn Store, rules: &RuleSet, change: &Change) -> Result<Option<LiveEvent>, Error> {
+        let player = match store.get_player(&change.player_uuid).await? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+
+        let what = match change.kind {
+            ChangeKind::Players => EventType::Joined,
+            ChangeKind::Deaths => EventType::Died,
+            ChangeKind::ExtraLives => EventType::ExtraLife,
+            ChangeKind::Offenses => EventType::Offense,
+        };
+
+        // the joined event has no id, the same as a player change.
+        let timeline = Timeline::build(store, &player, rules, None).await?;
+        let lives_remaining = timeline.lives_remaining();
+        let event = timeline.events.into_iter().find(|e| e.what == what && e.id == change.id);
+
+        Ok(event.map(|event| LiveEvent {
+            player_uuid: player.player_uuid,
+            player_name: player.name,
+            player_state: timeline.player_state,
+            survived_seconds: timeline.survived_seconds,
+            lives_remaining,
+            event,
+        }))
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+use crate::cache::CacheStats;
+use crate::store::PoolUsage;
+
+/// The upper bounds of the latency buckets, in seconds.
+const BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Requests that didn't match a route all get this label, so scanners can't add a series per path.
+const UNMATCHED: &str = "unmatched";
+
+/// Every route of `routes::api` as `route_label` writes it.
+const ROUTES: &[&str] = &[
+    "/healthz", "/readyz", "/metrics", "/timelines", "/timelines/{uuid}", "/players/by-name/{name}/timeline",
+    "/leaderboard", "/export/events.csv", "/export/events.ndjson", "/export/timelines.csv", "/export/timelines.ndjson",
+    "/seasons", "/events/stream", "/ws", "/deaths", "/offenses", "/extra-lives", "/players/{uuid}", "/admin/api-keys",
+    "/admin/api-keys/{id}", "/admin/seasons", "/admin/webhooks", "/admin/webhooks/{id}", "/admin/webhooks/dead-letters",
+    "/admin/import", "/admin/cache", "/admin/anomalies",
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Not cumulative, the count of each bucket on its own. The last one is `+Inf`.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; BUCKETS.len() + 1];
+        }
+        let bucket = BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    /// By method, route and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// By method and route.
+    request_durations: BTreeMap<(String, String), Histogram>,
+    /// By `Store` method.
+    store_durations: BTreeMap<&'static str, Histogram>,
+    timeline_builds: Histogram,
+}
+
+/// Everything counted since the process started. Recorded from anywhere with `global()`, the
+/// same way the logs are, so the store and timelines don't need a handle passed to them.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+/// The numbers worked out when `/metrics` is scraped instead of being counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Gauges {
+    pub players_alive: usize,
+    /// Out of lives, ghosts included.
+    pub players_dead: usize,
+    pub deaths: usize,
+    pub extra_lives: usize,
+    pub cache: Option<CacheStats>,
+    pub pool: Option<PoolUsage>,
+}
+
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    /// Counts a finished request, the path is turned into a route so every player isn't its own series.
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        let mut route = route_label(path);
+        if !ROUTES.contains(&route.as_str()) {
+            route = UNMATCHED.to_string();
+        }
+        let mut recorded = self.lock();
+        *recorded.requests.entry((method.to_string(), route.clone(), status)).or_default() += 1;
+        recorded.request_durations.entry((method.to_string(), route)).or_default().observe(elapsed);
+    }
+
+    pub fn observe_store(&self, method: &'static str, elapsed: Duration) {
+        self.lock().store_durations.entry(method).or_default().observe(elapsed);
+    }
+
+    pub fn observe_timeline_build(&self, elapsed: Duration) {
+        self.lock().timeline_builds.observe(elapsed);
+    }
+
+    /// Everything recorded and the gauges in the Prometheus text format.
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let recorded = self.lock();
+        let mut out = String::new();
+
+        header(&mut out, "hardcore_http_requests_total", "counter", "Requests by method, route and status.");
+        for ((method, route, status), count) in &recorded.requests {
+            let _ = writeln!(out, "hardcore_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method), escape(route), status, count);
+        }
+
+        header(&mut out, "hardcore_http_request_duration_seconds", "histogram", "Time to answer a request by method and route.");
+        for ((method, route), histogram) in &recorded.request_durations {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            write_histogram(&mut out, "hardcore_http_request_duration_seconds", &labels, histogram);
+        }
+
+        header(&mut out, "hardcore_store_duration_seconds", "histogram", "Time taken by each store method.");
+        for (method, histogram) in &recorded.store_durations {
+            write_histogram(&mut out, "hardcore_store_duration_seconds", &format!("method=\"{}\"", method), histogram);
+        }
+
+        header(&mut out, "hardcore_timeline_build_duration_seconds", "histogram", "Time to build a timeline from its rows.");
+        write_histogram(&mut out, "hardcore_timeline_build_duration_seconds", "", &recorded.timeline_builds);
+
+        if let Some(pool) = gauges.pool {
+            gauge(&mut out, "hardcore_db_pool_connections", "Open database connections.", pool.size as u64);
+            gauge(&mut out, "hardcore_db_pool_idle_connections", "Open database connections that aren't in use.", pool.idle as u64);
+            gauge(&mut out, "hardcore_db_pool_max_connections", "The most database connections the pool will open.", pool.max as u64);
+        }
+
+        if let Some(cache) = gauges.cache {
+            gauge(&mut out, "hardcore_timeline_cache_entries", "Timelines in the cache.", cache.entries as u64);
+            header(&mut out, "hardcore_timeline_cache_hits_total", "counter", "Timelines read from the cache.");
+            let _ = writeln!(out, "hardcore_timeline_cache_hits_total {}", cache.hits);
+            header(&mut out, "hardcore_timeline_cache_misses_total", "counter", "Timelines that had to be built.");
+            let _ = writeln!(out, "hardcore_timeline_cache_misses_total {}", cache.misses);
+        }
+
+        gauge(&mut out, "hardcore_players_alive", "Players with lives left.", gauges.players_alive as u64);
+        gauge(&mut out, "hardcore_players_dead", "Players out of lives.", gauges.players_dead as u64);
+        gauge(&mut out, "hardcore_deaths", "Deaths of every player.", gauges.deaths as u64);
+        gauge(&mut out, "hardcore_extra_lives", "Extra lives granted to every player.", gauges.extra_lives as u64);
+
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Recorded> {
+        // a panic while holding the lock can't leave a count half written, so ignore poisoning.
+        self.recorded.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Counts every request with the status it was answered with, so it has to wrap the filter
+/// that turns rejections into replies.
+pub fn record_requests() -> warp::log::Log<impl Fn(warp::log::Info<'_>) + Copy + Send + Sync> {
+    warp::log::custom(|info| {
+        global().observe_request(info.method().as_str(), info.path(), info.status().as_u16(), info.elapsed());
+    })
+}
+
+/// The path with the parts that change per request swapped for what they are, e.g.
+/// `/timelines/{uuid}` or `/players/by-name/{name}/timeline`.
+pub fn route_label(path: &str) -> String {
+    let mut route = String::new();
+    let mut previous = "";
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        route.push('/');
+        if previous == "by-name" {
+            route.push_str("{name}");
+        } else if Uuid::parse_str(segment).is_ok() {
+            route.push_str("{uuid}");
+        } else if segment.parse::<i64>().is_ok() {
+            route.push_str("{id}");
+        } else {
+            route.push_str(segment);
+        }
+        previous = segment;
+    }
+    if route.is_empty() {
+        route.push('/');
+    }
+    route
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (i, bound) in BUCKETS.iter().enumerate() {
+        cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+    }
+    let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, histogram.count);
+    let braced = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+    let _ = writeln!(out, "{}_sum{} {}", name, braced, histogram.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, braced, histogram.count);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_dont_have_players_in_them() {
+        assert_eq!(route_label("/timelines/00000000-0000-0000-0000-000000000001"), "/timelines/{uuid}");
+        assert_eq!(route_label("/players/by-name/Steve/timeline"), "/players/by-name/{name}/timeline");
+        assert_eq!(route_label("/admin/webhooks/12"), "/admin/webhooks/{id}");
+        assert_eq!(route_label("/"), "/");
+    }
+
+    #[test]
+    fn requests_are_counted_by_route() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/timelines/00000000-0000-0000-0000-000000000001", 200, Duration::from_millis(3));
+        metrics.observe_request("GET", "/timelines/00000000-0000-0000-0000-000000000002", 200, Duration::from_millis(30));
+        metrics.observe_request("GET", "/wp-admin", 404, Duration::from_millis(1));
+        metrics.observe_request("GET", "/timelines/00000000-0000-0000-0000-000000000003", 404, Duration::from_millis(1));
+
+        let text = metrics.render(&Gauges::default());
+
+        assert!(text.contains("hardcore_http_requests_total{method=\"GET\",route=\"/timelines/{uuid}\",status=\"200\"} 2"));
+        assert!(text.contains("hardcore_http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"} 1"));
+        assert!(text.contains("hardcore_http_requests_total{method=\"GET\",route=\"/timelines/{uuid}\",status=\"404\"} 1"));
+        assert!(text.contains("hardcore_http_request_duration_seconds_bucket{method=\"GET\",route=\"/timelines/{uuid}\",le=\"0.005\"} 2"));
+        assert!(text.contains("hardcore_http_request_duration_seconds_bucket{method=\"GET\",route=\"/timelines/{uuid}\",le=\"+Inf\"} 3"));
+    }
+
+    #[test]
+    fn gauges_are_written_with_the_counts() {
+        let gauges = Gauges { players_alive: 3, players_dead: 1, deaths: 7, extra_lives: 2, ..Gauges::default() };
+
+        let text = Metrics::default().render(&gauges);
+
+        assert!(text.contains("# TYPE hardcore_players_alive gauge\nhardcore_players_alive 3\n"));
+        assert!(text.contains("hardcore_deaths 7\n"));
+        assert!(!text.contains("hardcore_db_pool_connections"));
+    }
+}
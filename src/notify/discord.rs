@@ -0,0 +1,260 @@
+use std::env;
+use std::time::Duration;
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{event, Level};
+use crate::live::{Hub, LiveEvent};
+use crate::notify::format_span;
+use crate::timeline::EventType;
+
+/// Deaths older than this are history, e.g. from an import, and aren't posted.
+const STALE_MINUTES: i64 = 10;
+
+const DEFAULT_DEATH_TEMPLATE: &str =
+    "**{player}** died: {reason}. They survived {span} and have {lives} lives left.";
+const DEFAULT_ELIMINATION_TEMPLATE: &str =
+    "**{player}** has been eliminated: {reason}. Their last life lasted {span}, {survived} survived in total.";
+
+/// Where to post and what to say. Templates can use {player}, {reason}, {span}, {lives} and {survived}.
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    pub webhook_urls: Vec<String>,
+    pub death_template: String,
+    pub elimination_template: String,
+}
+
+impl DiscordConfig {
+    /// Loads the config from env variables, returns None when there are no webhook urls.
+    ///
+    /// DISCORD_WEBHOOK_URLS (comma separated), DISCORD_DEATH_TEMPLATE and DISCORD_ELIMINATION_TEMPLATE
+    pub fn from_env() -> Option<Self> {
+        let webhook_urls: Vec<String> = env::var("DISCORD_WEBHOOK_URLS").ok()?
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        if webhook_urls.is_empty() {
+            return None;
+        }
+
+        Some(DiscordConfig {
+            webhook_urls,
+            death_template: env::var("DISCORD_DEATH_TEMPLATE")
+                .unwrap_or_else(|_| DEFAULT_DEATH_TEMPLATE.to_string()),
+            elimination_template: env::var("DISCORD_ELIMINATION_TEMPLATE")
+                .unwrap_or_else(|_| DEFAULT_ELIMINATION_TEMPLATE.to_string()),
+        })
+    }
+
+    /// The message for the event, None when it isn't something discord is told about.
+    pub fn message(&self, live_event: &LiveEvent) -> Option<String> {
+        if live_event.event.what != EventType::Died {
+            return None;
+        }
+        // an old death is being imported, the server already heard about it.
+        if Utc::now() - live_event.event.stamp > chrono::Duration::minutes(STALE_MINUTES) {
+            return None;
+        }
+        // a death with no lives left, their elimination was already posted.
+        if live_event.event.no_lives {
+            return None;
+        }
+
+        // the lives right after the death, the player could have had an extra life since.
+        let lives = live_event.event.lives_after.total();
+        let template = if lives > 0 {
+            &self.death_template
+        } else {
+            &self.elimination_template
+        };
+
+        Some(template
+            .replace("{player}", &live_event.player_name)
+            .replace("{reason}", &live_event.event.context)
+            .replace("{span}", &format_span(live_event.event.span))
+            .replace("{lives}", &lives.to_string())
+            .replace("{survived}", &format_span(live_event.survived_seconds)))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct WebhookMessage<'a> {
+    content: &'a str,
+}
+
+/// Posts a message to every discord webhook for each death on the live feed.
+pub fn start(config: DiscordConfig, hub: &Hub) {
+    let mut live_events = hub.subscribe();
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+
+    tokio::spawn(async move {
+        loop {
+            let live_event = match live_events.recv().await {
+                Ok(res) => res,
+                Err(RecvError::Lagged(missed)) => {
+                    event!(target: "hardcore-api", Level::WARN, "discord notifier fell behind, missed {} events", missed);
+                    continue;
+                },
+                Err(RecvError::Closed) => break,
+            };
+
+            post(&config, &client, &live_event).await;
+        }
+    });
+}
+
+/// Posts the message for the event to every webhook, a webhook that fails is only logged.
+async fn post(config: &DiscordConfig, client: &reqwest::Client, live_event: &LiveEvent) {
+    let content = match config.message(live_event) {
+        Some(res) => res,
+        None => return,
+    };
+
+    for url in &config.webhook_urls {
+        let result = client.post(url)
+            .json(&WebhookMessage { content: &content })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            event!(target: "hardcore-api", Level::ERROR, "couldn't post to discord: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+    use warp::Filter;
+    use crate::timeline::{Lives, PlayerState, TimelineEvent};
+    use super::*;
+
+    fn config() -> DiscordConfig {
+        DiscordConfig {
+            webhook_urls: vec![],
+            death_template: DEFAULT_DEATH_TEMPLATE.to_string(),
+            elimination_template: DEFAULT_ELIMINATION_TEMPLATE.to_string(),
+        }
+    }
+
+    fn death(stamp: DateTime<Utc>, span: i32, lives: i32) -> LiveEvent {
+        LiveEvent {
+            player_uuid: Uuid::nil(),
+            player_name: "Steve".to_string(),
+            player_state: if lives > 0 { PlayerState::Alive } else { PlayerState::Dead },
+            survived_seconds: 7200,
+            lives_remaining: lives,
+            event: TimelineEvent {
+                id: Some(1),
+                stamp,
+                what: EventType::Died,
+                context: "fell from a high place".to_string(),
+                playtime: 7200,
+                span,
+                unranked: false,
+                no_lives: false,
+                lives_after: Lives { ranked: lives, unranked: 0 },
+            },
+        }
+    }
+
+    #[test]
+    fn a_death_with_lives_left() {
+        let message = config().message(&death(Utc::now(), 3900, 2));
+
+        assert_eq!(message.as_deref(), Some("**Steve** died: fell from a high place. They survived 1h 5m and have 2 lives left."));
+    }
+
+    #[test]
+    fn the_last_death_is_an_elimination() {
+        let message = config().message(&death(Utc::now(), 600, 0));
+
+        assert_eq!(message.as_deref(), Some("**Steve** has been eliminated: fell from a high place. Their last life lasted 10m, 2h 0m survived in total."));
+    }
+
+    #[test]
+    fn old_deaths_are_not_posted() {
+        let stamp = Utc::now() - chrono::Duration::minutes(STALE_MINUTES + 1);
+
+        assert_eq!(config().message(&death(stamp, 600, 2)), None);
+    }
+
+    #[test]
+    fn deaths_with_no_lives_left_are_not_posted() {
+        let mut live_event = death(Utc::now(), -300, 0);
+        live_event.event.no_lives = true;
+
+        assert_eq!(config().message(&live_event), None);
+    }
+
+    #[test]
+    fn a_late_elimination_is_posted_as_one_after_an_extra_life() {
+        // the player has been given a life since the death was sent.
+        let mut live_event = death(Utc::now(), 600, 0);
+        live_event.player_state = PlayerState::Alive;
+        live_event.lives_remaining = 1;
+
+        let message = config().message(&live_event);
+
+        assert_eq!(message.as_deref(), Some("**Steve** has been eliminated: fell from a high place. Their last life lasted 10m, 2h 0m survived in total."));
+    }
+
+    #[test]
+    fn deaths_with_a_backwards_playtime_are_posted() {
+        let message = config().message(&death(Utc::now(), -300, 1));
+
+        assert_eq!(message.as_deref(), Some("**Steve** died: fell from a high place. They survived 0s and have 1 lives left."));
+    }
+
+    #[test]
+    fn only_deaths_are_posted() {
+        let mut live_event = death(Utc::now(), 0, 3);
+        live_event.event.what = EventType::ExtraLife;
+
+        assert_eq!(config().message(&live_event), None);
+    }
+
+    #[test]
+    fn templates_can_use_every_placeholder() {
+        let config = DiscordConfig {
+            death_template: "{player}|{reason}|{span}|{lives}|{survived}".to_string(),
+            ..config()
+        };
+
+        let message = config.message(&death(Utc::now(), 45, 1));
+
+        assert_eq!(message.as_deref(), Some("Steve|fell from a high place|45s|1|2h 0m"));
+    }
+
+    #[tokio::test]
+    async fn posts_the_message_to_every_webhook() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stand_in = warp::post()
+            .and(warp::path("webhook"))
+            .and(warp::body::json())
+            .map(move |body: serde_json::Value| {
+                sender.send(body).unwrap();
+                warp::reply()
+            });
+        let (addr, server) = warp::serve(stand_in).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let config = DiscordConfig {
+            webhook_urls: vec![format!("http://{}/webhook", addr), format!("http://{}/webhook", addr)],
+            ..config()
+        };
+        post(&config, &reqwest::Client::new(), &death(Utc::now(), 600, 2)).await;
+
+        for _ in 0..2 {
+            let body = receiver.recv().await.unwrap();
+            assert_eq!(body["content"], "**Steve** died: fell from a high place. They survived 10m and have 2 lives left.");
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+}
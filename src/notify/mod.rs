@@ -0,0 +1,17 @@
+pub mod discord;
+pub mod webhooks;
+
+/// Formats a span in seconds the way people read it, e.g. "2h 5m".
+pub fn format_span(seconds: i32) -> String {
+    let seconds = seconds.max(0);
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
@@ -0,0 +1,241 @@
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{event, Level};
+use crate::config::env_or;
+use crate::live::{Hub, LiveEvent};
+use crate::store::DynStore;
+use crate::types::webhook::Webhook;
+
+/// The header with the hex HMAC-SHA256 of the body, signed with the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Hardcore-Signature";
+/// The header with the event type, so subscribers can route without parsing the body.
+pub const EVENT_HEADER: &str = "X-Hardcore-Event";
+
+/// The longest wait between delivery attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// How many times a delivery is tried before it goes to the dead letters.
+    pub max_attempts: i32,
+    /// The wait before the first retry, it doubles after every attempt up to `MAX_BACKOFF`.
+    pub backoff: Duration,
+}
+
+impl RetryConfig {
+    /// Loads the config from env variables, WEBHOOK_MAX_ATTEMPTS and WEBHOOK_BACKOFF_SECONDS.
+    pub fn from_env() -> Self {
+        RetryConfig {
+            max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 5),
+            backoff: Duration::from_secs(env_or("WEBHOOK_BACKOFF_SECONDS", 1)),
+        }
+    }
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    // hmac accepts keys of any length.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Sends every live event to the webhooks that want it.
+pub fn start(store: DynStore, hub: &Hub, retry: RetryConfig) {
+    let mut live_events = hub.subscribe();
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+
+    tokio::spawn(async move {
+        loop {
+            let live_event = match live_events.recv().await {
+                Ok(res) => res,
+                Err(RecvError::Lagged(missed)) => {
+                    event!(target: "hardcore-api", Level::WARN, "webhooks fell behind, missed {} events", missed);
+                    continue;
+                },
+                Err(RecvError::Closed) => break,
+            };
+
+            let webhooks = match store.get_webhooks().await {
+                Ok(res) => res,
+                Err(e) => {
+                    event!(target: "hardcore-api", Level::ERROR, "couldn't load webhooks: {:?}", e);
+                    continue;
+                },
+            };
+
+            for webhook in webhooks.into_iter().filter(|w| w.wants(live_event.event.what)) {
+                // each delivery retries on its own, so a slow subscriber doesn't hold up the others.
+                tokio::spawn(deliver(store.clone(), client.clone(), webhook, live_event.clone(), retry));
+            }
+        }
+    });
+}
+
+async fn deliver(store: DynStore, client: reqwest::Client, webhook: Webhook, live_event: LiveEvent, retry: RetryConfig) {
+    let payload = match serde_json::to_string(&live_event) {
+        Ok(res) => res,
+        Err(e) => {
+            event!(target: "hardcore-api", Level::ERROR, "couldn't serialize webhook payload: {}", e);
+            return;
+        },
+    };
+    let signature = sign(&webhook.secret, payload.as_bytes());
+
+    let mut backoff = retry.backoff.min(MAX_BACKOFF);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = client.post(&webhook.url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .header(EVENT_HEADER, live_event.event.what.to_string())
+            .body(payload.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        let error = match result {
+            Ok(_) => return,
+            Err(e) => e.to_string(),
+        };
+
+        if attempts >= retry.max_attempts {
+            event!(target: "hardcore-api", Level::ERROR, "webhook {} failed after {} attempts: {}", webhook.id, attempts, error);
+            if let Err(e) = store.add_dead_letter(webhook.id, &payload, &error, attempts).await {
+                event!(target: "hardcore-api", Level::ERROR, "couldn't save dead letter for webhook {}: {:?}", webhook.id, e);
+            }
+            return;
+        }
+
+        event!(target: "hardcore-api", Level::WARN, "webhook {} failed, retrying in {:?}: {}", webhook.id, backoff, error);
+        tokio::time::sleep(backoff).await;
+        backoff = next_backoff(backoff);
+    }
+}
+
+/// Twice the wait, without going past `MAX_BACKOFF` however many attempts there are.
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+    use chrono::Utc;
+    use uuid::Uuid;
+    use warp::Filter;
+    use warp::http::StatusCode;
+    use crate::store::memory::MemoryStore;
+    use crate::timeline::{EventType, Lives, PlayerState, TimelineEvent};
+    use super::*;
+
+    /// What the stand-in subscriber was sent.
+    #[derive(Debug)]
+    struct Received {
+        at: Instant,
+        signature: String,
+        body: Vec<u8>,
+    }
+
+    /// A subscriber that fails the first `failures` deliveries and then accepts them.
+    fn stand_in(failures: usize) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let log = received.clone();
+        let route = warp::post()
+            .and(warp::header::<String>(SIGNATURE_HEADER))
+            .and(warp::body::bytes())
+            .map(move |signature: String, body: warp::hyper::body::Bytes| {
+                let mut received = log.lock().unwrap();
+                received.push(Received { at: Instant::now(), signature, body: body.to_vec() });
+                let status = if received.len() <= failures { StatusCode::INTERNAL_SERVER_ERROR } else { StatusCode::OK };
+                warp::reply::with_status(warp::reply(), status)
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/", addr), received)
+    }
+
+    fn webhook(url: String) -> Webhook {
+        Webhook { id: 1, url, secret: "secret".to_string(), event_types: vec![], created: Utc::now() }
+    }
+
+    fn live_event() -> LiveEvent {
+        LiveEvent {
+            player_uuid: Uuid::nil(),
+            player_name: "Steve".to_string(),
+            player_state: PlayerState::Alive,
+            survived_seconds: 0,
+            lives_remaining: 3,
+            event: TimelineEvent {
+                id: Some(1),
+                stamp: Utc::now(),
+                what: EventType::Offense,
+                context: "griefing".to_string(),
+                playtime: 0,
+                span: 0,
+                unranked: false,
+                no_lives: false,
+                lives_after: Lives::default(),
+            },
+        }
+    }
+
+    fn retry(max_attempts: i32) -> RetryConfig {
+        RetryConfig { max_attempts, backoff: Duration::from_millis(20) }
+    }
+
+    #[test]
+    fn sign_is_hmac_sha256() {
+        // test case 2 of RFC 4231.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+    }
+
+    #[test]
+    fn the_backoff_stops_doubling_at_the_max() {
+        assert_eq!(next_backoff(Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(next_backoff(Duration::from_secs(200)), MAX_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff_until_it_is_delivered() {
+        let store: DynStore = Arc::new(MemoryStore::default());
+        let (url, received) = stand_in(2);
+
+        deliver(store.clone(), reqwest::Client::new(), webhook(url), live_event(), retry(5)).await;
+
+        assert!(store.get_dead_letters().await.unwrap().is_empty());
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        // the wait doubles after every attempt.
+        assert!(received[1].at - received[0].at >= Duration::from_millis(20));
+        assert!(received[2].at - received[1].at >= Duration::from_millis(40));
+        for delivery in received.iter() {
+            assert_eq!(delivery.signature, sign("secret", &delivery.body));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_dead_letter_is_saved_after_max_attempts() {
+        let store: DynStore = Arc::new(MemoryStore::default());
+        let (url, received) = stand_in(usize::MAX);
+
+        deliver(store.clone(), reqwest::Client::new(), webhook(url), live_event(), retry(3)).await;
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+        let dead_letters = store.get_dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].webhook_id, 1);
+        assert_eq!(dead_letters[0].attempts, 3);
+        assert_eq!(dead_letters[0].payload.as_bytes(), received.lock().unwrap()[0].body);
+    }
+}
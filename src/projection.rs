@@ -0,0 +1,145 @@
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{event, Level};
+use uuid::Uuid;
+use crate::config::env_or;
+use crate::errors::Error;
+use crate::live::Hub;
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+use crate::timeline::Timeline;
+
+/// The alive event's span grows while a player plays without a new row, so every projection is
+/// rebuilt this often as well as when a player's rows change.
+const DEFAULT_REFRESH_SECONDS: u64 = 300;
+
+/// Keeps `player_timeline_stats` and `timeline_events` up to date with the timelines, so the
+/// leaderboard and other tools can read them instead of building every timeline.
+#[derive(Clone, Debug)]
+pub struct Projector {
+    store: DynStore,
+    rules: RuleSet,
+}
+
+impl Projector {
+    pub fn new(store: DynStore, rules: RuleSet) -> Self {
+        Projector { store, rules }
+    }
+
+    /// PROJECTIONS turns the projections on, PROJECTION_REFRESH_SECONDS is how often they are all rebuilt.
+    pub fn from_env(store: DynStore, rules: RuleSet) -> Option<(Self, Duration)> {
+        if !env_or("PROJECTIONS", false) {
+            return None;
+        }
+
+        let refresh = Duration::from_secs(env_or("PROJECTION_REFRESH_SECONDS", DEFAULT_REFRESH_SECONDS));
+        Some((Self::new(store, rules), refresh))
+    }
+
+    /// Rebuilds every projection now and every `refresh`, and a player's whenever the store gets
+    /// a new row for them that wasn't imported.
+    pub fn start(&self, hub: &Hub, refresh: Duration) {
+        let mut changes = hub.changes();
+        let projector = self.clone();
+
+        tokio::spawn(async move {
+            // the first tick is straight away, so the tables are filled when the api starts.
+            let mut interval = tokio::time::interval(refresh);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => projector.rebuild_all_logged().await,
+                    change = changes.recv() => match change {
+                        // the import re-projects everything once it's done.
+                        Ok(change) if change.imported => {},
+                        Ok(change) => {
+                            if let Err(e) = projector.rebuild_player(&change.player_uuid).await {
+                                event!(target: "hardcore-api", Level::ERROR, "couldn't rebuild the projection of {}: {:?}", change.player_uuid, e);
+                            }
+                        },
+                        Err(RecvError::Lagged(missed)) => {
+                            event!(target: "hardcore-api", Level::WARN, "projections fell behind, missed {} changes", missed);
+                            // any of the players could have changed.
+                            projector.rebuild_all_logged().await;
+                        },
+                        Err(RecvError::Closed) => break,
+                    },
+                }
+            }
+        });
+    }
+
+    /// A player that doesn't exist has nothing to project.
+    pub async fn rebuild_player(&self, player_uuid: &Uuid) -> Result<(), Error> {
+        let player = match self.store.get_player(player_uuid).await? {
+            Some(res) => res,
+            None => return Ok(()),
+        };
+        let timeline = Timeline::build(self.store.as_ref(), &player, &self.rules, None).await?;
+        self.store.save_projection(&player, &timeline).await
+    }
+
+    /// Rebuilds every player's projection, returns how many there were.
+    pub async fn rebuild_all(&self) -> Result<usize, Error> {
+        let mut rebuilt = 0;
+        for (player, timeline) in Timeline::build_each(self.store.as_ref(), &self.rules, None).await? {
+            self.store.save_projection(&player, &timeline).await?;
+            rebuilt += 1;
+        }
+        Ok(rebuilt)
+    }
+
+    async fn rebuild_all_logged(&self) {
+        match self.rebuild_all().await {
+            Ok(rebuilt) => event!(target: "hardcore-api", Level::DEBUG, "rebuilt {} projections", rebuilt),
+            Err(e) => event!(target: "hardcore-api", Level::ERROR, "couldn't rebuild the projections: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use chrono::Utc;
+    use crate::leaderboard::{PageQuery, SortBy, SortOrder};
+    use crate::store::memory::{Fixture, MemoryStore};
+    use crate::test_support::steve;
+    use crate::timeline::PlayerState;
+    use crate::types::death::NewDeath;
+    use super::*;
+
+    fn all() -> PageQuery {
+        PageQuery { sort: SortBy::Survived, order: SortOrder::Desc, state: None, after: None, limit: 50 }
+    }
+
+    fn projector() -> Projector {
+        let store = MemoryStore::new(Fixture { players: vec![steve()], ..Fixture::default() });
+        Projector::new(Arc::new(store), RuleSet::default())
+    }
+
+    #[tokio::test]
+    async fn rebuild_all_projects_every_player() {
+        let projector = projector();
+
+        assert_eq!(projector.rebuild_all().await.unwrap(), 1);
+
+        let rows = projector.store.get_projections(&all()).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].player_name, "Steve");
+        assert_eq!(rows[0].survived_seconds, 3600);
+        assert_eq!(rows[0].player_state, PlayerState::Alive);
+    }
+
+    #[tokio::test]
+    async fn rebuilding_a_player_replaces_their_row() {
+        let projector = projector();
+        projector.rebuild_all().await.unwrap();
+
+        let death = NewDeath { player_uuid: steve().player_uuid, stamp: Utc::now(), playtime: 36_000, reason: "fell".to_string(), imported: false };
+        projector.store.add_death(&death).await.unwrap();
+        projector.rebuild_player(&steve().player_uuid).await.unwrap();
+
+        let rows = projector.store.get_projections(&all()).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].deaths, 1);
+    }
+}
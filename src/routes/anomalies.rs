@@ -0,0 +1,17 @@
+use crate::anomalies;
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+use tracing::{event, instrument, Level};
+
+#[instrument]
+pub async fn get_anomalies(store: DynStore, rules: RuleSet) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "checking every timeline for anomalies");
+
+    let anomalies = match anomalies::check_all(store.as_ref(), &rules).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&anomalies))
+}
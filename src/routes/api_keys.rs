@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use crate::auth;
+use crate::auth::{ApiKey, Scope};
+use crate::errors::Error;
+use crate::store::DynStore;
+use tracing::{event, instrument, Level};
+
+#[derive(Deserialize, Debug)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// The only time the key itself is returned, after this only the hash is kept.
+#[derive(Serialize, Debug)]
+pub struct CreatedApiKey {
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+#[instrument]
+pub async fn get_api_keys(store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading api keys");
+
+    let api_keys = match store.get_api_keys().await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&api_keys))
+}
+
+#[instrument(skip(new_api_key))]
+pub async fn post_api_key(store: DynStore, new_api_key: NewApiKey) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "creating api key {}", new_api_key.name);
+
+    if new_api_key.name.trim().is_empty() {
+        return Err(warp::reject::custom(Error::InvalidInput("name can not be empty".to_string())));
+    }
+    if new_api_key.scopes.is_empty() {
+        return Err(warp::reject::custom(Error::InvalidInput("a key needs at least one scope".to_string())));
+    }
+
+    let key = auth::generate_key();
+    let api_key = match store.add_api_key(&new_api_key.name, &auth::hash_key(&key), &new_api_key.scopes).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&CreatedApiKey { key, api_key }),
+        StatusCode::CREATED,
+    ))
+}
+
+#[instrument]
+pub async fn delete_api_key(id: i64, store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "revoking api key {}", id);
+
+    match store.revoke_api_key(id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(warp::reject::custom(Error::NotFound("Api key".to_string()))),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
@@ -0,0 +1,100 @@
+use futures_util::{Stream, StreamExt};
+use warp::http::Response;
+use warp::hyper::Body;
+use crate::errors::Error;
+use crate::export;
+use crate::leaderboard::{self, LeaderboardRow, SortBy, SortOrder};
+use crate::routes::timeline::{load_season, TimelineQuery};
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+use crate::timeline::Timeline;
+use tracing::{event, instrument, Level};
+
+const CSV: &str = "text/csv; charset=utf-8";
+const NDJSON: &str = "application/x-ndjson";
+
+/// Every event of every timeline as CSV, each player's rows are loaded when they are written.
+#[instrument]
+pub async fn get_events_csv(query: TimelineQuery, store: DynStore, rules: RuleSet) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "exporting events as csv");
+
+    let (season, rules) = load_season(store.as_ref(), &query, &rules).await?;
+    let timelines = match Timeline::stream_each(store.clone(), &rules, season.as_ref()).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let header = futures_util::stream::once(async { Ok(export::csv_header(export::EVENT_COLUMNS)) });
+    let lines = timelines.map(|res| res.map(|(player, timeline)| export::events_csv(&player, &timeline)));
+    stream(CSV, header.chain(lines))
+}
+
+/// Every event of every timeline as JSON lines, each player's rows are loaded when they are written.
+#[instrument]
+pub async fn get_events_ndjson(query: TimelineQuery, store: DynStore, rules: RuleSet) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "exporting events as ndjson");
+
+    let (season, rules) = load_season(store.as_ref(), &query, &rules).await?;
+    let timelines = match Timeline::stream_each(store.clone(), &rules, season.as_ref()).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let lines = timelines.map(|res| res.map(|(player, timeline)| export::events_ndjson(&player, &timeline)));
+    stream(NDJSON, lines)
+}
+
+/// Every player's leaderboard row as CSV, ranked by survived time.
+#[instrument]
+pub async fn get_timelines_csv(query: TimelineQuery, store: DynStore, rules: RuleSet) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "exporting timelines as csv");
+
+    let rows = ranked_rows(&query, store, rules).await?;
+    let lines = std::iter::once(export::csv_header(export::TIMELINE_COLUMNS))
+        .chain(rows.into_iter().map(|row| export::row_csv(&row)));
+    stream(CSV, futures_util::stream::iter(lines.map(Ok)))
+}
+
+/// Every player's leaderboard row as JSON lines, ranked by survived time.
+#[instrument]
+pub async fn get_timelines_ndjson(query: TimelineQuery, store: DynStore, rules: RuleSet) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "exporting timelines as ndjson");
+
+    let rows = ranked_rows(&query, store, rules).await?;
+    let lines = rows.into_iter().map(|row| export::row_ndjson(&row));
+    stream(NDJSON, futures_util::stream::iter(lines.map(Ok)))
+}
+
+/// The rows have to be ranked before the first one is written, but only the rows are kept,
+/// each timeline's events are dropped once its row is made.
+async fn ranked_rows(query: &TimelineQuery, store: DynStore, rules: RuleSet) -> Result<Vec<LeaderboardRow>, warp::Rejection> {
+    let (season, rules) = load_season(store.as_ref(), query, &rules).await?;
+    let timelines = match Timeline::build_each(store.as_ref(), &rules, season.as_ref()).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let mut rows: Vec<LeaderboardRow> = timelines
+        .map(|(player, timeline)| LeaderboardRow::from_timeline(&player, &timeline))
+        .collect();
+    leaderboard::sort(&mut rows, SortBy::Survived, SortOrder::Desc);
+    leaderboard::rank(&mut rows, None);
+    Ok(rows)
+}
+
+/// Writes each chunk to the response as the stream makes it. The status has already been sent
+/// when a chunk fails, so the error is logged and the response is cut off.
+fn stream(content_type: &str, chunks: impl Stream<Item = Result<String, Error>> + Send + 'static) -> Result<Response<Body>, warp::Rejection> {
+    let body = Body::wrap_stream(chunks.map(|chunk| chunk.map_err(|e| {
+        event!(target: "hardcore-api", Level::ERROR, "couldn't finish the export: {:?}", e);
+        e.to_string()
+    })));
+    match Response::builder().header("content-type", content_type).body(body) {
+        Ok(res) => Ok(res),
+        Err(e) => Err(warp::reject::custom(Error::Internal(format!("couldn't build export response: {}", e)))),
+    }
+}
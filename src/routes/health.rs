@@ -0,0 +1,25 @@
+use serde_json::json;
+use warp::http::StatusCode;
+use crate::store::DynStore;
+use tracing::instrument;
+
+/// The process is up, nothing else is checked.
+pub async fn get_healthz() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&json!({ "status": "ok" })))
+}
+
+/// The database can be reached, is migrated and has every table, a 503 with what is wrong when not.
+#[instrument]
+pub async fn get_readyz(store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+    let readiness = store.readiness().await;
+    let (status, code) = if readiness.is_ready() {
+        ("ready", StatusCode::OK)
+    } else {
+        ("not ready", StatusCode::SERVICE_UNAVAILABLE)
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "status": status, "checks": readiness })),
+        code,
+    ))
+}
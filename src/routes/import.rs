@@ -0,0 +1,47 @@
+use serde::Deserialize;
+use warp::hyper::body::Bytes;
+use crate::errors::Error;
+use crate::import::{self, ImportFormat, ImportKind};
+use crate::projection::Projector;
+use crate::store::DynStore;
+use tracing::{event, instrument, Level};
+
+#[derive(Deserialize, Debug)]
+pub struct ImportQuery {
+    pub kind: ImportKind,
+    pub format: ImportFormat,
+    /// Check every row without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The imported rows aren't projected one at a time, the projections are all rebuilt once at the end.
+#[instrument(skip(body))]
+pub async fn post_import(query: ImportQuery, store: DynStore, projector: Option<Projector>, body: Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "importing {} bytes of {:?}", body.len(), query.kind);
+
+    let data = match std::str::from_utf8(&body) {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(Error::InvalidInput(format!("the body isn't utf-8: {}", e)))),
+    };
+
+    let rows = match import::parse(query.kind, query.format, data) {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let report = match import::import(store.as_ref(), rows, query.dry_run).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    // the rows are already in, a failed rebuild is caught up by the next refresh.
+    if let Some(projector) = projector.filter(|_| !report.dry_run && report.imported > 0) {
+        if let Err(e) = projector.rebuild_all().await {
+            event!(target: "hardcore-api", Level::ERROR, "couldn't rebuild the projections after the import: {:?}", e);
+        }
+    }
+
+    Ok(warp::reply::json(&report))
+}
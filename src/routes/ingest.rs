@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use warp::http::StatusCode;
+use uuid::Uuid;
+use crate::cache::TimelineCache;
+use crate::errors::Error;
+use crate::routes;
+use crate::store::{DynStore, Store};
+use crate::types::death::NewDeath;
+use crate::types::extra_life::NewExtraLife;
+use crate::types::offense::NewOffense;
+use crate::types::player::PlayerUpdate;
+use tracing::{event, instrument, Level};
+
+/// Events can only be added for players that are already in the players table.
+async fn require_player(store: &dyn Store, player_uuid: &Uuid) -> Result<(), warp::Rejection> {
+    match store.get_player(player_uuid).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(warp::reject::custom(Error::NotFound("Player".to_string()))),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[instrument]
+pub async fn post_death(store: DynStore, death: NewDeath, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "adding death for {}", death.player_uuid);
+
+    if let Err(e) = death.validate() {
+        return Err(warp::reject::custom(e));
+    }
+    require_player(store.as_ref(), &death.player_uuid).await?;
+
+    let death = match store.add_death(&death).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    // the store sends a change as well, but it can arrive after the next read.
+    cache.invalidate(&death.player_uuid);
+
+    Ok(warp::reply::with_status(warp::reply::json(&death), StatusCode::CREATED))
+}
+
+#[instrument]
+pub async fn post_offense(store: DynStore, offense: NewOffense, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "adding offense for {}", offense.player_uuid);
+
+    if let Err(e) = offense.validate() {
+        return Err(warp::reject::custom(e));
+    }
+    require_player(store.as_ref(), &offense.player_uuid).await?;
+
+    let offense = match store.add_offense(&offense).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    // the store sends a change as well, but it can arrive after the next read.
+    cache.invalidate(&offense.player_uuid);
+
+    Ok(warp::reply::with_status(warp::reply::json(&offense), StatusCode::CREATED))
+}
+
+#[instrument]
+pub async fn post_extra_life(store: DynStore, extra_life: NewExtraLife, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "adding extra life for {}", extra_life.player_uuid);
+
+    if let Err(e) = extra_life.validate() {
+        return Err(warp::reject::custom(e));
+    }
+    require_player(store.as_ref(), &extra_life.player_uuid).await?;
+
+    let extra_life = match store.add_extra_life(&extra_life).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    // the store sends a change as well, but it can arrive after the next read.
+    cache.invalidate(&extra_life.player_uuid);
+
+    Ok(warp::reply::with_status(warp::reply::json(&extra_life), StatusCode::CREATED))
+}
+
+#[instrument]
+pub async fn put_player(player_uuid: String, store: DynStore, player: PlayerUpdate, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "updating player {}", player_uuid);
+
+    let player_uuid = routes::parse_uuid(&player_uuid)?;
+
+    if let Err(e) = player.validate() {
+        return Err(warp::reject::custom(e));
+    }
+
+    let player = match store.upsert_player(&player_uuid, &player).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    // updating a player doesn't send a change.
+    cache.invalidate(&player.player_uuid);
+
+    Ok(warp::reply::json(&player))
+}
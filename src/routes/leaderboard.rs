@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::cache::TimelineCache;
+use crate::errors::Error;
+use crate::leaderboard::{self, Cursor, LeaderboardRow, PageQuery, SortBy, SortOrder, StateFilter};
+use crate::projection::Projector;
+use crate::routes::timeline::{load_season, TimelineQuery};
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+use tracing::{event, instrument, Level};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct LeaderboardQuery {
+    #[serde(default)]
+    pub sort: SortBy,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub state: Option<StateFilter>,
+    pub limit: Option<usize>,
+    /// The `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+    pub season: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LeaderboardPage {
+    pub rows: Vec<LeaderboardRow>,
+    /// Pass as `cursor` to get the next page, empty on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// With projections on, the whole server's page is selected from `player_timeline_stats`
+/// instead of building every timeline. A season's is always built.
+#[instrument]
+pub async fn get_leaderboard(
+    query: LeaderboardQuery,
+    store: DynStore,
+    rules: RuleSet,
+    cache: Arc<TimelineCache>,
+    projector: Option<Projector>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading leaderboard");
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(warp::reject::custom(Error::BadQueryParam(format!("limit must be between 1 and {}", MAX_LIMIT))));
+    }
+
+    let after = match query.cursor.as_deref().map(str::parse::<Cursor>).transpose() {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(Error::BadQueryParam(e))),
+    };
+    // the cursor's value is of its own sort, it would start the page in the wrong place.
+    if let Some(cursor) = after.as_ref().filter(|cursor| cursor.sort != query.sort || cursor.order != query.order) {
+        return Err(warp::reject::custom(Error::BadQueryParam(format!(
+            "the cursor is for sort={}&order={}", cursor.sort, cursor.order))));
+    }
+
+    let season_query = TimelineQuery { season: query.season.clone() };
+    let (season, rules) = load_season(store.as_ref(), &season_query, &rules).await?;
+
+    // one more than the limit, there is a next page when it's there.
+    let page = PageQuery { sort: query.sort, order: query.order, state: query.state, after, limit: limit + 1 };
+    let rows = if projector.is_some() && season.is_none() {
+        store.get_projections(&page).await
+    } else {
+        cache.get_all(store.as_ref(), &rules, season.as_ref()).await.map(|timelines| leaderboard::page(
+            timelines.iter().map(|(player, timeline)| LeaderboardRow::from_timeline(player, timeline)).collect(),
+            &page,
+        ))
+    };
+    let mut rows: Vec<LeaderboardRow> = match rows {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    leaderboard::rank(&mut rows, page.after.as_ref());
+    let next_cursor = next_cursor(&rows, limit, query.sort, query.order);
+    rows.truncate(limit);
+
+    Ok(warp::reply::json(&LeaderboardPage { rows, next_cursor }))
+}
+
+/// The rows are loaded with one more than the limit, there is a next page when it's there.
+fn next_cursor(rows: &[LeaderboardRow], limit: usize, sort: SortBy, order: SortOrder) -> Option<String> {
+    if rows.len() > limit {
+        Some(Cursor::after(&rows[limit - 1], sort, order).to_string())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+use warp::sse::Event;
+use warp::ws::{Message, WebSocket, Ws};
+use crate::live::{Hub, LiveEvent};
+use crate::timeline::EventType;
+use tracing::{event, instrument, Level};
+
+/// Streams every new timeline event as server-sent events, the event name is the event type.
+#[instrument]
+pub async fn get_event_stream(hub: Hub) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "opening event stream");
+
+    // a lagging client skips the events it missed.
+    let stream = BroadcastStream::new(hub.subscribe())
+        .filter_map(|live_event| async move { live_event.ok() })
+        .map(|live_event| {
+            let event = Event::default().event(live_event.event.what.to_string());
+            match event.json_data(&live_event) {
+                Ok(res) => Ok::<Event, Infallible>(res),
+                Err(_) => Ok(Event::default().comment("couldn't serialize event")),
+            }
+        });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+}
+
+/// A message from a websocket client, changing what it is subscribed to.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        players: Vec<Uuid>,
+        #[serde(default)]
+        events: Vec<EventType>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        players: Vec<Uuid>,
+        #[serde(default)]
+        events: Vec<EventType>,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage<'a> {
+    /// Sent after every subscribe or unsubscribe, with everything the client is now subscribed to.
+    Subscribed {
+        players: &'a HashSet<Uuid>,
+        events: &'a HashSet<EventType>,
+    },
+    Event(&'a LiveEvent),
+    Error {
+        message: String,
+    },
+}
+
+/// What a client gets: with only players, every event of those players; with only event types,
+/// those events of every player; with both, those events of those players. Nothing until it
+/// subscribes to something.
+#[derive(Debug, Default)]
+struct Subscription {
+    players: HashSet<Uuid>,
+    events: HashSet<EventType>,
+}
+
+impl Subscription {
+    fn wants(&self, live_event: &LiveEvent) -> bool {
+        if self.players.is_empty() && self.events.is_empty() {
+            return false;
+        }
+        let player = self.players.is_empty() || self.players.contains(&live_event.player_uuid);
+        let what = self.events.is_empty() || self.events.contains(&live_event.event.what);
+        player && what
+    }
+
+    fn apply(&mut self, message: ClientMessage) {
+        match message {
+            ClientMessage::Subscribe { players, events } => {
+                self.players.extend(players);
+                self.events.extend(events);
+            },
+            ClientMessage::Unsubscribe { players, events } => {
+                for player in &players {
+                    self.players.remove(player);
+                }
+                for what in &events {
+                    self.events.remove(what);
+                }
+            },
+        }
+    }
+}
+
+#[instrument(skip(ws))]
+pub async fn get_ws(ws: Ws, hub: Hub) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "opening websocket");
+
+    Ok(ws.on_upgrade(move |socket| ws_client(socket, hub)))
+}
+
+async fn ws_client(socket: WebSocket, hub: Hub) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut live_events = hub.subscribe();
+    let mut subscription = Subscription::default();
+
+    loop {
+        let reply = tokio::select! {
+            message = receiver.next() => {
+                let message = match message {
+                    Some(Ok(res)) => res,
+                    // the client went away.
+                    Some(Err(_)) | None => break,
+                };
+                if message.is_close() {
+                    break;
+                }
+                if !message.is_text() {
+                    continue;
+                }
+
+                match serde_json::from_slice::<ClientMessage>(message.as_bytes()) {
+                    Ok(client_message) => {
+                        subscription.apply(client_message);
+                        serde_json::to_string(&ServerMessage::Subscribed {
+                            players: &subscription.players,
+                            events: &subscription.events,
+                        })
+                    },
+                    Err(e) => serde_json::to_string(&ServerMessage::Error { message: e.to_string() }),
+                }
+            },
+            live_event = live_events.recv() => {
+                let live_event = match live_event {
+                    Ok(res) => res,
+                    Err(RecvError::Lagged(missed)) => {
+                        event!(target: "hardcore-api", Level::WARN, "websocket fell behind, missed {} events", missed);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+                if !subscription.wants(&live_event) {
+                    continue;
+                }
+                serde_json::to_string(&ServerMessage::Event(&live_event))
+            },
+        };
+
+        let reply = match reply {
+            Ok(res) => res,
+            Err(e) => {
+                event!(target: "hardcore-api", Level::ERROR, "couldn't serialize websocket message: {}", e);
+                continue;
+            },
+        };
+        if sender.send(Message::text(reply)).await.is_err() {
+            break;
+        }
+    }
+
+    event!(target: "hardcore-api", Level::INFO, "closing websocket");
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use crate::live::LiveEvent;
+    use crate::timeline::{Lives, PlayerState, TimelineEvent};
+    use super::*;
+
+    fn live_event(player: u128, what: EventType) -> LiveEvent {
+        LiveEvent {
+            player_uuid: Uuid::from_u128(player),
+            player_name: "Steve".to_string(),
+            player_state: PlayerState::Alive,
+            survived_seconds: 0,
+            lives_remaining: 3,
+            event: TimelineEvent {
+                id: Some(1),
+                stamp: Utc::now(),
+                what,
+                context: "fell".to_string(),
+                playtime: 0,
+                span: 0,
+                unranked: false,
+                no_lives: false,
+                lives_after: Lives::default(),
+            },
+        }
+    }
+
+    fn subscription(players: &[u128], events: &[EventType]) -> Subscription {
+        Subscription {
+            players: players.iter().map(|p| Uuid::from_u128(*p)).collect(),
+            events: events.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn players_and_event_types_both_have_to_match() {
+        let deaths_of_one = subscription(&[1], &[EventType::Died]);
+
+        assert!(deaths_of_one.wants(&live_event(1, EventType::Died)));
+        assert!(!deaths_of_one.wants(&live_event(2, EventType::Died)));
+        assert!(!deaths_of_one.wants(&live_event(1, EventType::Offense)));
+    }
+
+    #[test]
+    fn an_empty_set_matches_everything_unless_both_are_empty() {
+        assert!(subscription(&[1], &[]).wants(&live_event(1, EventType::Offense)));
+        assert!(subscription(&[], &[EventType::Died]).wants(&live_event(2, EventType::Died)));
+        assert!(!subscription(&[], &[]).wants(&live_event(1, EventType::Died)));
+    }
+}
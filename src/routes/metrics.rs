@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use crate::cache::TimelineCache;
+use crate::metrics::{self, Gauges};
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+use crate::timeline::{EventType, PlayerState};
+use tracing::instrument;
+
+/// Everything recorded since the process started, in the Prometheus text format. The player
+/// gauges come from the cached timelines, a scrape isn't counted as cache hits or misses.
+#[instrument]
+pub async fn get_metrics(store: DynStore, rules: RuleSet, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    let timelines = match cache.get_all_uncounted(store.as_ref(), &rules, None).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let mut gauges = Gauges { cache: Some(cache.stats()), pool: store.pool_usage(), ..Gauges::default() };
+    for (_, timeline) in &timelines {
+        match timeline.player_state {
+            PlayerState::Alive => gauges.players_alive += 1,
+            _ => gauges.players_dead += 1,
+        }
+        gauges.deaths += timeline.events.iter().filter(|e| e.what == EventType::Died).count();
+        gauges.extra_lives += timeline.events.iter().filter(|e| e.what == EventType::ExtraLife).count();
+    }
+
+    Ok(warp::reply::with_header(
+        metrics::global().render(&gauges),
+        "content-type",
+        "text/plain; version=0.0.4; charset=utf-8",
+    ))
+}
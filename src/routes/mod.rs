@@ -1 +1,748 @@
-pub mod timeline;
\ No newline at end of file
+pub mod anomalies;
+pub mod api_keys;
+pub mod export;
+pub mod health;
+pub mod import;
+pub mod ingest;
+pub mod leaderboard;
+pub mod live;
+pub mod metrics;
+pub mod seasons;
+pub mod timeline;
+pub mod webhooks;
+
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::Filter;
+use crate::auth::{Auth, Scope};
+use crate::cache::TimelineCache;
+use crate::errors::Error;
+use crate::live::Hub;
+use crate::projection::Projector;
+use crate::rules::RuleSet;
+use crate::store::DynStore;
+
+/// The largest request body the write endpoints will accept.
+const MAX_BODY_BYTES: u64 = 1024 * 16;
+/// The largest file that can be imported in one request.
+const MAX_IMPORT_BYTES: u64 = 1024 * 1024 * 16;
+
+/// Path parameters are taken as strings so a bad uuid is a 400 instead of a missing route.
+pub fn parse_uuid(value: &str) -> Result<Uuid, warp::Rejection> {
+    Uuid::parse_str(value).map_err(|_| warp::reject::custom(Error::InvalidUuid(value.to_string())))
+}
+
+/// Every route of the api, without the CORS, logging and error replies added by main.
+pub fn api(
+    store: DynStore,
+    rules: RuleSet,
+    hub: Hub,
+    auth: Auth,
+    cache: Arc<TimelineCache>,
+    projector: Option<Projector>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+
+    let store_filter = warp::any().map(move || store.clone());
+    let rules_filter = warp::any().map(move || rules.clone());
+    let hub_filter = warp::any().map(move || hub.clone());
+    let cache_filter = warp::any().map(move || cache.clone());
+    let projector_filter = warp::any().map(move || projector.clone());
+
+    let read_auth = auth.require(Scope::Read);
+    let ingest_auth = auth.require(Scope::Ingest);
+    let admin_auth = auth.require(Scope::Admin);
+
+    // probes from the orchestrator don't have an api key.
+    let get_healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and_then(health::get_healthz);
+
+    let get_readyz = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(health::get_readyz);
+
+    let get_timelines = warp::get()
+        .and(warp::path("timelines"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and(cache_filter.clone())
+        .and_then(timeline::get_timelines);
+
+    let get_timeline = warp::get()
+        .and(warp::path("timelines"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and(cache_filter.clone())
+        .and_then(timeline::get_timeline);
+
+    let get_timeline_by_name = warp::get()
+        .and(warp::path("players"))
+        .and(warp::path("by-name"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("timeline"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and(cache_filter.clone())
+        .and_then(timeline::get_timeline_by_name);
+
+    let get_leaderboard = warp::get()
+        .and(warp::path("leaderboard"))
+        .and(warp::path::end())
+        .and(warp::query::<leaderboard::LeaderboardQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and(cache_filter.clone())
+        .and(projector_filter.clone())
+        .and_then(leaderboard::get_leaderboard);
+
+    let get_events_csv = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path("events.csv"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and_then(export::get_events_csv);
+
+    let get_events_ndjson = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path("events.ndjson"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and_then(export::get_events_ndjson);
+
+    let get_timelines_csv = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path("timelines.csv"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and_then(export::get_timelines_csv);
+
+    let get_timelines_ndjson = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path("timelines.ndjson"))
+        .and(warp::path::end())
+        .and(warp::query::<timeline::TimelineQuery>())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and_then(export::get_timelines_ndjson);
+
+    let get_seasons = warp::get()
+        .and(warp::path("seasons"))
+        .and(warp::path::end())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and_then(seasons::get_seasons);
+
+    let get_event_stream = warp::get()
+        .and(warp::path("events"))
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(read_auth.clone())
+        .and(hub_filter.clone())
+        .and_then(live::get_event_stream);
+
+    let get_ws = warp::path("ws")
+        .and(warp::path::end())
+        .and(read_auth.clone())
+        .and(warp::ws())
+        .and(hub_filter)
+        .and_then(live::get_ws);
+
+    let post_death = warp::post()
+        .and(warp::path("deaths"))
+        .and(warp::path::end())
+        .and(ingest_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and(cache_filter.clone())
+        .and_then(ingest::post_death);
+
+    let post_offense = warp::post()
+        .and(warp::path("offenses"))
+        .and(warp::path::end())
+        .and(ingest_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and(cache_filter.clone())
+        .and_then(ingest::post_offense);
+
+    let post_extra_life = warp::post()
+        .and(warp::path("extra-lives"))
+        .and(warp::path::end())
+        .and(ingest_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and(cache_filter.clone())
+        .and_then(ingest::post_extra_life);
+
+    let put_player = warp::put()
+        .and(warp::path("players"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(ingest_auth)
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and(cache_filter.clone())
+        .and_then(ingest::put_player);
+
+    let get_api_keys = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(api_keys::get_api_keys);
+
+    let post_api_key = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and_then(api_keys::post_api_key);
+
+    let delete_api_key = warp::delete()
+        .and(warp::path("admin"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::param::<i64>())
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(api_keys::delete_api_key);
+
+    let post_season = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("seasons"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and_then(seasons::post_season);
+
+    let get_webhooks = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(webhooks::get_webhooks);
+
+    let post_webhook = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .and_then(webhooks::post_webhook);
+
+    let delete_webhook = warp::delete()
+        .and(warp::path("admin"))
+        .and(warp::path("webhooks"))
+        .and(warp::path::param::<i64>())
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(webhooks::delete_webhook);
+
+    let get_dead_letters = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("webhooks"))
+        .and(warp::path("dead-letters"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(webhooks::get_dead_letters);
+
+    let post_import = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(warp::query::<import::ImportQuery>())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and(projector_filter)
+        .and(warp::body::content_length_limit(MAX_IMPORT_BYTES))
+        .and(warp::body::bytes())
+        .and_then(import::post_import);
+
+    let get_metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(read_auth.clone())
+        .and(store_filter.clone())
+        .and(rules_filter.clone())
+        .and(cache_filter.clone())
+        .and_then(metrics::get_metrics);
+
+    let get_cache_stats = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("cache"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(cache_filter)
+        .and_then(timeline::get_cache_stats);
+
+    let get_anomalies = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("anomalies"))
+        .and(warp::path::end())
+        .and(admin_auth)
+        .and(store_filter)
+        .and(rules_filter)
+        .and_then(anomalies::get_anomalies);
+
+    get_healthz
+        .or(get_readyz)
+        .or(get_timelines)
+        .or(get_timeline)
+        .or(get_timeline_by_name)
+        .or(get_leaderboard)
+        .or(get_events_csv)
+        .or(get_events_ndjson)
+        .or(get_timelines_csv)
+        .or(get_timelines_ndjson)
+        .or(get_seasons)
+        .or(get_event_stream)
+        .or(get_ws)
+        .or(post_death)
+        .or(post_offense)
+        .or(post_extra_life)
+        .or(put_player)
+        .or(get_api_keys)
+        .or(post_api_key)
+        .or(delete_api_key)
+        .or(post_season)
+        .or(get_webhooks)
+        .or(post_webhook)
+        .or(delete_webhook)
+        .or(get_dead_letters)
+        .or(post_import)
+        .or(get_cache_stats)
+        .or(get_anomalies)
+        .or(get_metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use chrono::{TimeZone, Utc};
+    use serde_json::{json, Value};
+    use warp::http::StatusCode;
+    use crate::errors::return_error;
+    use crate::store::memory::{Fixture, MemoryStore};
+    use crate::test_support::steve;
+    use crate::types::player::Player;
+    use super::*;
+
+    const ADMIN_KEY: &str = "test-admin-key";
+
+    /// The api over a memory store with one player.
+    async fn test_api() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let store: DynStore = Arc::new(MemoryStore::new(Fixture { players: vec![steve()], ..Fixture::default() }));
+        api_over(store).await
+    }
+
+    async fn api_over(store: DynStore) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let rules = RuleSet::default();
+        let hub = Hub::start(store.clone(), rules.clone()).await.unwrap();
+        let auth = Auth::new(store.clone(), Some(ADMIN_KEY.to_string()), true);
+        let cache = Arc::new(TimelineCache::new(Duration::from_secs(60)));
+        TimelineCache::start(cache.clone(), &hub);
+        api(store, rules, hub, auth, cache, None).recover(return_error)
+    }
+
+    fn body(response: &warp::http::Response<warp::hyper::body::Bytes>) -> Value {
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_timeline_by_uuid() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .path(&format!("/timelines/{}", steve().player_uuid))
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let timeline = body(&response);
+        assert_eq!(timeline["player_name"], "Steve");
+        assert_eq!(timeline["player_state"], "Alive");
+        assert_eq!(timeline["survived_seconds"], 72_000 / 20);
+    }
+
+    #[tokio::test]
+    async fn get_timeline_of_an_unknown_player_is_a_404() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .path(&format!("/timelines/{}", Uuid::from_u128(2)))
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body(&response)["code"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn post_season_takes_unix_seconds() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/seasons")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .json(&json!({"name": "one", "starts": 1_700_000_000, "ends": "1710000000"}))
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let season = body(&response);
+        assert_eq!(season["starts"], "2023-11-14T22:13:20Z");
+        assert_eq!(season["ends"], "2024-03-09T16:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn get_timeline_of_a_player_that_joined_after_the_season_is_a_404() {
+        let api = test_api().await;
+
+        // steve joined in september 2024, after the season ended.
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/seasons")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .json(&json!({"name": "one", "starts": "2024-01-01T00:00:00Z", "ends": "2024-06-01T00:00:00Z"}))
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = warp::test::request()
+            .path(&format!("/timelines/{}?season=one", steve().player_uuid))
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = warp::test::request()
+            .path("/players/by-name/Steve/timeline?season=one")
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn post_death_needs_an_ingest_key() {
+        let api = test_api().await;
+        let death = json!({"player_uuid": steve().player_uuid, "stamp": 1_725_003_600, "playtime": 36_000, "reason": "fell"});
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/deaths")
+            .json(&death)
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/deaths")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .json(&death)
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = warp::test::request()
+            .path(&format!("/timelines/{}", steve().player_uuid))
+            .reply(&api)
+            .await;
+        let timeline = body(&response);
+        assert_eq!(timeline["events"][1]["what"], "Died");
+        assert_eq!(timeline["events"][1]["span"], 36_000 / 20);
+    }
+
+    #[tokio::test]
+    async fn post_death_for_an_unknown_player_is_a_404() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/deaths")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .json(&json!({"player_uuid": Uuid::from_u128(2), "stamp": "2024-09-01T01:00:00Z", "playtime": 0, "reason": "fell"}))
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn leaderboard_pages_start_after_the_cursor() {
+        // two players with the same playtime are ordered by name.
+        let players: Vec<Player> = [("Alex", 3), ("Bob", 2), ("carl", 2), ("Dan", 1), ("Eve", 0)].iter()
+            .enumerate()
+            .map(|(i, (name, hours))| Player {
+                player_uuid: Uuid::from_u128(i as u128 + 1),
+                name: name.to_string(),
+                joined: Utc.timestamp_opt(1_725_000_000, 0).unwrap(),
+                playtime: hours * 72_000,
+            })
+            .collect();
+        let api = api_over(Arc::new(MemoryStore::new(Fixture { players, ..Fixture::default() }))).await;
+
+        let mut names = vec![];
+        let mut ranks = vec![];
+        let mut path = "/leaderboard?limit=2".to_string();
+        loop {
+            let response = warp::test::request().path(&path).reply(&api).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let page = body(&response);
+            for row in page["rows"].as_array().unwrap() {
+                names.push(row["player_name"].as_str().unwrap().to_string());
+                ranks.push(row["rank"].as_u64().unwrap());
+            }
+            match page["next_cursor"].as_str() {
+                Some(cursor) => path = format!("/leaderboard?limit=2&cursor={}", cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(names, ["Alex", "Bob", "carl", "Dan", "Eve"]);
+        assert_eq!(ranks, [1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn export_events_as_csv() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .path("/export/events.csv")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/csv; charset=utf-8");
+        let csv = String::from_utf8(response.body().to_vec()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "player_uuid,player_name,id,stamp,what,context,playtime,span,unranked,no_lives,ranked_lives_after,unranked_lives_after");
+        // steve has joined and is alive.
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].contains(",Alive,"));
+    }
+
+    #[tokio::test]
+    async fn export_timelines_as_ndjson() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .path("/export/timelines.ndjson")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let ndjson = String::from_utf8(response.body().to_vec()).unwrap();
+        let rows: Vec<Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["rank"], 1);
+        assert_eq!(rows[0]["player_name"], "Steve");
+    }
+
+    #[tokio::test]
+    async fn post_import_of_a_csv() {
+        let api = test_api().await;
+        let csv = "player_uuid,stamp,playtime,reason\n00000000-0000-0000-0000-000000000001,1725003600,36000,fell\n";
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/import?kind=deaths&format=csv")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .body(csv)
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(&response)["imported"], 1);
+
+        // the same file again is only duplicates.
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/import?kind=deaths&format=csv")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .body(csv)
+            .reply(&api)
+            .await;
+        assert_eq!(body(&response)["imported"], 0);
+        assert_eq!(body(&response)["duplicates"], 1);
+    }
+
+    #[tokio::test]
+    async fn an_import_isnt_live_and_is_projected_once_it_is_in() {
+        let store: DynStore = Arc::new(MemoryStore::new(Fixture { players: vec![steve()], ..Fixture::default() }));
+        let rules = RuleSet::default();
+        let hub = Hub::start(store.clone(), rules.clone()).await.unwrap();
+        let auth = Auth::new(store.clone(), Some(ADMIN_KEY.to_string()), true);
+        let cache = Arc::new(TimelineCache::new(Duration::from_secs(60)));
+        let projector = Projector::new(store.clone(), rules.clone());
+        let mut live_events = hub.subscribe();
+        let api = api(store, rules, hub, auth, cache, Some(projector)).recover(return_error);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/admin/import?kind=deaths&format=csv")
+            .header("authorization", format!("Bearer {}", ADMIN_KEY))
+            .body("player_uuid,stamp,playtime,reason\n00000000-0000-0000-0000-000000000001,1725003600,36000,fell\n")
+            .reply(&api)
+            .await;
+        assert_eq!(body(&response)["imported"], 1);
+
+        // nothing ran the projector but the import.
+        let leaderboard = warp::test::request().path("/leaderboard").reply(&api).await;
+        assert_eq!(body(&leaderboard)["rows"][0]["deaths"], 1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(live_events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn leaderboard_with_a_bad_cursor_is_a_400() {
+        let api = test_api().await;
+
+        let response = warp::test::request()
+            .path("/leaderboard?cursor=50")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn leaderboard_ranks_carry_on_from_the_cursor() {
+        let api = test_api().await;
+
+        // Steve survived longer than the cursor's 0 seconds, so he is after it in ascending order.
+        let response = warp::test::request()
+            .path(&format!("/leaderboard?order=asc&cursor=survived:asc:10:0:a:{}", Uuid::from_u128(0)))
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(&response)["rows"][0]["player_name"], "Steve");
+        assert_eq!(body(&response)["rows"][0]["rank"], 11);
+    }
+
+    #[tokio::test]
+    async fn leaderboard_with_a_cursor_of_another_sort_is_a_400() {
+        let api = test_api().await;
+        let cursor = format!("survived:desc:10:3600:a:{}", Uuid::from_u128(0));
+
+        for path in [format!("/leaderboard?sort=deaths&cursor={}", cursor), format!("/leaderboard?order=asc&cursor={}", cursor)] {
+            let response = warp::test::request().path(&path).reply(&api).await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn leaderboard_is_read_from_the_projections_when_they_are_on() {
+        let store: DynStore = Arc::new(MemoryStore::new(Fixture { players: vec![steve()], ..Fixture::default() }));
+        let rules = RuleSet::default();
+        let hub = Hub::start(store.clone(), rules.clone()).await.unwrap();
+        let auth = Auth::new(store.clone(), Some(ADMIN_KEY.to_string()), true);
+        let cache = Arc::new(TimelineCache::new(Duration::from_secs(60)));
+        let projector = Projector::new(store.clone(), rules.clone());
+        let api = api(store, rules, hub, auth, cache, Some(projector.clone())).recover(return_error);
+
+        let before = warp::test::request().path("/leaderboard").reply(&api).await;
+        projector.rebuild_all().await.unwrap();
+        let after = warp::test::request().path("/leaderboard").reply(&api).await;
+
+        assert_eq!(body(&before)["rows"], json!([]));
+        assert_eq!(body(&after)["rows"][0]["player_name"], "Steve");
+        assert_eq!(body(&after)["rows"][0]["rank"], 1);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_prometheus_text() {
+        let api = test_api().await;
+
+        // the only miss, scraping the metrics isn't counted.
+        let timelines = warp::test::request().path("/timelines").reply(&api).await;
+        assert_eq!(timelines.status(), StatusCode::OK);
+        warp::test::request().path("/metrics").reply(&api).await;
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&api)
+            .await;
+        let text = String::from_utf8(response.body().to_vec()).unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        assert!(text.contains("hardcore_players_alive 1\n"));
+        assert!(text.contains("hardcore_timeline_cache_misses_total 1\n"));
+        assert!(text.contains("hardcore_timeline_cache_hits_total 0\n"));
+    }
+
+    #[tokio::test]
+    async fn probes_dont_need_a_key() {
+        let store: DynStore = Arc::new(MemoryStore::default());
+        let rules = RuleSet::default();
+        let hub = Hub::start(store.clone(), rules.clone()).await.unwrap();
+        // private reads, so anything else would be a 401.
+        let auth = Auth::new(store.clone(), Some(ADMIN_KEY.to_string()), false);
+        let cache = Arc::new(TimelineCache::new(Duration::from_secs(60)));
+        let api = api(store, rules, hub, auth, cache, None).recover(return_error);
+
+        let healthz = warp::test::request().path("/healthz").reply(&api).await;
+        let readyz = warp::test::request().path("/readyz").reply(&api).await;
+
+        assert_eq!(healthz.status(), StatusCode::OK);
+        assert_eq!(body(&healthz)["status"], "ok");
+        assert_eq!(readyz.status(), StatusCode::OK);
+        assert_eq!(body(&readyz)["checks"]["database"], true);
+        assert_eq!(body(&readyz)["checks"]["missing_tables"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn requests_are_counted_with_the_status_they_were_answered_with() {
+        let store: DynStore = Arc::new(MemoryStore::default());
+        let api = api_over(store).await.with(crate::metrics::record_requests());
+
+        let response = warp::test::request()
+            .path("/admin/anomalies")
+            .reply(&api)
+            .await;
+        let text = crate::metrics::global().render(&crate::metrics::Gauges::default());
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(text.contains("hardcore_http_requests_total{method=\"GET\",route=\"/admin/anomalies\",status=\"401\"}"));
+    }
+}
@@ -0,0 +1,34 @@
+use warp::http::StatusCode;
+use crate::store::DynStore;
+use crate::types::season::NewSeason;
+use tracing::{event, instrument, Level};
+
+#[instrument]
+pub async fn get_seasons(store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading seasons");
+
+    let seasons = match store.get_seasons().await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&seasons))
+}
+
+#[instrument]
+pub async fn post_season(store: DynStore, new_season: NewSeason) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "creating season {}", new_season.name);
+
+    if let Err(e) = new_season.validate() {
+        return Err(warp::reject::custom(e));
+    }
+
+    let season = match store.add_season(&new_season).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&season), StatusCode::CREATED))
+}
@@ -1,30 +1,108 @@
-use crate::store::Store;
-use crate::timeline;
+use std::sync::Arc;
+use serde::Deserialize;
+use crate::cache::TimelineCache;
+use crate::errors::Error;
+use crate::routes;
+use crate::rules::RuleSet;
+use crate::store::{DynStore, Store};
 use crate::timeline::Timeline;
+use crate::types::season::Season;
 use tracing::{event, instrument, Level};
 
+#[derive(Deserialize, Debug)]
+pub struct TimelineQuery {
+    /// The name of the season to limit the timelines to.
+    pub season: Option<String>,
+}
+
+/// Looks up the season asked for, and the rules to use for it.
+pub async fn load_season(store: &dyn Store, query: &TimelineQuery, rules: &RuleSet) -> Result<(Option<Season>, RuleSet), warp::Rejection> {
+    let name = match &query.season {
+        Some(name) => name,
+        None => return Ok((None, rules.clone())),
+    };
+
+    match store.get_season(name).await {
+        Ok(Some(season)) => {
+            let rules = season.rules_or(rules);
+            Ok((Some(season), rules))
+        },
+        Ok(None) => Err(warp::reject::custom(Error::NotFound("Season".to_string()))),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
 #[instrument]
-pub async fn get_timelines(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn get_timelines(query: TimelineQuery, store: DynStore, rules: RuleSet, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
 
     event!(target: "hardcore-api", Level::INFO, "loading timelines");
 
-    let players = match store.get_players().await {
-        Ok(res) => res,
+    let (season, rules) = load_season(store.as_ref(), &query, &rules).await?;
+
+    let mut timelines: Vec<Timeline> = match cache.get_all(store.as_ref(), &rules, season.as_ref()).await {
+        Ok(res) => res.into_iter().map(|(_, timeline)| timeline).collect(),
         Err(e) => return Err(warp::reject::custom(e)),
     };
-    let mut timelines: Vec<Timeline> = vec![];
-
-    for player in players {
-        let timeline = match timeline::Timeline::build(&store, &player).await {
-            Ok(r) => r,
-            Err(e) => return Err(warp::reject::custom(e)),
-        };
-        //print!("{:?}", timeline);
-        timelines.push(timeline)
-    }
 
     timelines.sort();
     timelines.reverse();
 
     Ok(warp::reply::json(&timelines))
 }
+
+#[instrument]
+pub async fn get_timeline(player_uuid: String, query: TimelineQuery, store: DynStore, rules: RuleSet, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading timeline for {}", player_uuid);
+
+    let player_uuid = routes::parse_uuid(&player_uuid)?;
+
+    let player = match store.get_player(&player_uuid).await {
+        Ok(Some(res)) => res,
+        Ok(None) => return Err(warp::reject::custom(Error::NotFound("Player".to_string()))),
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let (season, rules) = load_season(store.as_ref(), &query, &rules).await?;
+    // the same as the list, a player that joined after the season ended wasn't part of it.
+    if season.as_ref().is_some_and(|s| !s.includes(&player)) {
+        return Err(warp::reject::custom(Error::NotFound("Player".to_string())));
+    }
+
+    let timeline = match cache.get(store.as_ref(), &player, &rules, season.as_ref()).await {
+        Ok(r) => r,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&timeline))
+}
+
+#[instrument]
+pub async fn get_timeline_by_name(name: String, query: TimelineQuery, store: DynStore, rules: RuleSet, cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading timeline for {}", name);
+
+    let player = match store.get_player_by_name(&name).await {
+        Ok(Some(res)) => res,
+        Ok(None) => return Err(warp::reject::custom(Error::NotFound("Player".to_string()))),
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let (season, rules) = load_season(store.as_ref(), &query, &rules).await?;
+    // the same as the list, a player that joined after the season ended wasn't part of it.
+    if season.as_ref().is_some_and(|s| !s.includes(&player)) {
+        return Err(warp::reject::custom(Error::NotFound("Player".to_string())));
+    }
+
+    let timeline = match cache.get(store.as_ref(), &player, &rules, season.as_ref()).await {
+        Ok(r) => r,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&timeline))
+}
+
+#[instrument]
+pub async fn get_cache_stats(cache: Arc<TimelineCache>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&cache.stats()))
+}
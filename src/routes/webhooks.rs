@@ -0,0 +1,73 @@
+use serde::Serialize;
+use warp::http::StatusCode;
+use crate::auth;
+use crate::errors::Error;
+use crate::store::DynStore;
+use crate::types::webhook::{NewWebhook, Webhook};
+use tracing::{event, instrument, Level};
+
+/// The only time the secret is returned.
+#[derive(Serialize, Debug)]
+pub struct CreatedWebhook {
+    pub secret: String,
+    pub webhook: Webhook,
+}
+
+#[instrument]
+pub async fn get_webhooks(store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading webhooks");
+
+    let webhooks = match store.get_webhooks().await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&webhooks))
+}
+
+#[instrument]
+pub async fn post_webhook(store: DynStore, new_webhook: NewWebhook) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "creating webhook for {}", new_webhook.url);
+
+    if let Err(e) = new_webhook.validate() {
+        return Err(warp::reject::custom(e));
+    }
+
+    let secret = auth::generate_key();
+    let webhook = match store.add_webhook(&new_webhook, &secret).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&CreatedWebhook { secret, webhook }),
+        StatusCode::CREATED,
+    ))
+}
+
+#[instrument]
+pub async fn delete_webhook(id: i64, store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "deleting webhook {}", id);
+
+    match store.delete_webhook(id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(warp::reject::custom(Error::NotFound("Webhook".to_string()))),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[instrument]
+pub async fn get_dead_letters(store: DynStore) -> Result<impl warp::Reply, warp::Rejection> {
+
+    event!(target: "hardcore-api", Level::INFO, "loading webhook dead letters");
+
+    let dead_letters = match store.get_dead_letters().await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&dead_letters))
+}
@@ -0,0 +1,66 @@
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::config::{env_or, env_parse};
+
+/// The rules used to count a player's lives. Both the player state and the
+/// life spans on a timeline are calculated from the same rule set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RuleSet {
+    /// How many ranked lives a player starts with.
+    pub starting_lives: i32,
+    /// Should PAID extra lives be ranked lives instead of unranked lives.
+    pub paid_lives_ranked: bool,
+    /// The most extra lives a player can be granted, any extra lives after this are ignored.
+    pub max_extra_lives: Option<i32>,
+    pub ghost: GhostBehaviour,
+}
+
+/// What a player that is out of lives (a ghost) is doing until they get an extra life.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GhostBehaviour {
+    /// Ghosts are spectating, the time spent as a ghost does not count towards their next life.
+    Spectate,
+    /// Ghosts keep playing, the time spent as a ghost counts towards their next life.
+    Roam,
+}
+
+impl FromStr for GhostBehaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spectate" => Ok(GhostBehaviour::Spectate),
+            "roam" => Ok(GhostBehaviour::Roam),
+            _ => Err(format!("unknown ghost behaviour '{}'", s)),
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            starting_lives: 3,
+            paid_lives_ranked: false,
+            max_extra_lives: None,
+            ghost: GhostBehaviour::Spectate,
+        }
+    }
+}
+
+impl RuleSet {
+    /// Loads the rules from env variables, anything that is not set uses the default.
+    ///
+    /// STARTING_LIVES, PAID_LIVES_RANKED, MAX_EXTRA_LIVES and GHOST_BEHAVIOUR
+    pub fn from_env() -> Self {
+        let defaults = RuleSet::default();
+
+        RuleSet {
+            starting_lives: env_or("STARTING_LIVES", defaults.starting_lives),
+            paid_lives_ranked: env_or("PAID_LIVES_RANKED", defaults.paid_lives_ranked),
+            max_extra_lives: env_parse("MAX_EXTRA_LIVES").or(defaults.max_extra_lives),
+            ghost: env_or("GHOST_BEHAVIOUR", defaults.ghost),
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{RwLock, TryLockError};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::auth::{ApiKey, Scope};
+use crate::errors::Error;
+use crate::leaderboard::{self, LeaderboardRow, PageQuery};
+use crate::store::{group_by_player, Change, ChangeKind, PoolUsage, Readiness, Store, CHANGE_BUFFER};
+use crate::types::death::{Death, NewDeath};
+use crate::types::extra_life::{ExtraLife, NewExtraLife};
+use crate::types::offense::{NewOffense, Offense};
+use crate::types::player::{Player, PlayerUpdate};
+use crate::types::season::{NewSeason, Season};
+use crate::types::webhook::{DeadLetter, NewWebhook, Webhook};
+use crate::timeline::Timeline;
+
+/// The rows of every table, this is also the format of a fixture file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Fixture {
+    pub players: Vec<Player>,
+    pub deaths: Vec<Death>,
+    pub extra_lives: Vec<ExtraLife>,
+    pub offenses: Vec<Offense>,
+    pub seasons: Vec<Season>,
+}
+
+impl Fixture {
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(res) => res,
+            Err(e) => return Err(Error::Internal(format!("couldn't read fixture {}: {}", path, e))),
+        };
+        match serde_json::from_str(&data) {
+            Ok(fixture) => Ok(fixture),
+            Err(e) => Err(Error::Internal(format!("couldn't parse fixture {}: {}", path, e))),
+        }
+    }
+}
+
+struct ApiKeyRow {
+    api_key: ApiKey,
+    key_hash: String,
+    revoked: bool,
+}
+
+#[derive(Default)]
+struct Tables {
+    players: Vec<Player>,
+    deaths: Vec<Death>,
+    extra_lives: Vec<ExtraLife>,
+    offenses: Vec<Offense>,
+    seasons: Vec<Season>,
+    api_keys: Vec<ApiKeyRow>,
+    webhooks: Vec<Webhook>,
+    dead_letters: Vec<DeadLetter>,
+    /// Only the stats, the projected events are only there to be read with SQL.
+    projections: HashMap<Uuid, LeaderboardRow>,
+    next_id: i64,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// A store that keeps everything in memory, nothing is saved when the process exits.
+pub struct MemoryStore {
+    tables: RwLock<Tables>,
+    changes: broadcast::Sender<Change>,
+}
+
+/// Only the counts, the handlers log the store they're given and the tables have webhook
+/// secrets and key hashes in them.
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // it can be logged while the tables are being written, so it doesn't wait for them.
+        let tables = match self.tables.try_read() {
+            Ok(res) => res,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return f.debug_struct("MemoryStore").finish_non_exhaustive(),
+        };
+        f.debug_struct("MemoryStore")
+            .field("players", &tables.players.len())
+            .field("deaths", &tables.deaths.len())
+            .field("extra_lives", &tables.extra_lives.len())
+            .field("offenses", &tables.offenses.len())
+            .field("seasons", &tables.seasons.len())
+            .field("api_keys", &tables.api_keys.len())
+            .field("webhooks", &tables.webhooks.len())
+            .field("dead_letters", &tables.dead_letters.len())
+            .field("projections", &tables.projections.len())
+            .finish()
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(Fixture::default())
+    }
+}
+
+impl MemoryStore {
+    pub fn new(fixture: Fixture) -> Self {
+        // new rows get ids after the largest id in the fixture.
+        let next_id = fixture.deaths.iter().map(|d| d.id)
+            .chain(fixture.extra_lives.iter().map(|e| e.id))
+            .chain(fixture.offenses.iter().map(|o| o.id))
+            .chain(fixture.seasons.iter().map(|s| s.id))
+            .max()
+            .unwrap_or(0);
+
+        MemoryStore {
+            tables: RwLock::new(Tables {
+                players: fixture.players,
+                deaths: fixture.deaths,
+                extra_lives: fixture.extra_lives,
+                offenses: fixture.offenses,
+                seasons: fixture.seasons,
+                api_keys: vec![],
+                webhooks: vec![],
+                dead_letters: vec![],
+                projections: HashMap::new(),
+                next_id,
+            }),
+            changes: broadcast::channel(CHANGE_BUFFER).0,
+        }
+    }
+
+    /// Loads a JSON fixture file, see `Fixture` for the format.
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        Ok(Self::new(Fixture::from_file(path)?))
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Tables> {
+        // a panic while holding the lock can't leave the tables half written, so ignore poisoning.
+        self.tables.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Tables> {
+        self.tables.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify(&self, kind: ChangeKind, player_uuid: Uuid, id: Option<i64>, imported: bool) {
+        // an error only means nothing is watching.
+        let _ = self.changes.send(Change { kind, player_uuid, id, imported });
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn run_migrations(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        None
+    }
+
+    async fn readiness(&self) -> Readiness {
+        Readiness { database: true, ..Readiness::default() }
+    }
+
+    async fn get_players(&self) -> Result<Vec<Player>, Error> {
+        Ok(self.read().players.clone())
+    }
+
+    async fn get_player(&self, player_uuid: &Uuid) -> Result<Option<Player>, Error> {
+        Ok(self.read().players.iter().find(|p| p.player_uuid == *player_uuid).cloned())
+    }
+
+    async fn get_player_by_name(&self, name: &str) -> Result<Option<Player>, Error> {
+        Ok(self.read().players.iter().find(|p| p.name.eq_ignore_ascii_case(name)).cloned())
+    }
+
+    async fn get_player_deaths(&self, player_uuid: &Uuid) -> Result<Vec<Death>, Error> {
+        Ok(self.read().deaths.iter().filter(|d| d.player_uuid == *player_uuid).cloned().collect())
+    }
+
+    async fn get_player_extra_lives(&self, player_uuid: &Uuid) -> Result<Vec<ExtraLife>, Error> {
+        Ok(self.read().extra_lives.iter().filter(|e| e.player_uuid == *player_uuid).cloned().collect())
+    }
+
+    async fn get_player_offenses(&self, player_uuid: &Uuid) -> Result<Vec<Offense>, Error> {
+        Ok(self.read().offenses.iter().filter(|o| o.player_uuid == *player_uuid).cloned().collect())
+    }
+
+    async fn get_deaths(&self) -> Result<HashMap<Uuid, Vec<Death>>, Error> {
+        Ok(group_by_player(self.read().deaths.clone(), |d| d.player_uuid))
+    }
+
+    async fn get_extra_lives(&self) -> Result<HashMap<Uuid, Vec<ExtraLife>>, Error> {
+        Ok(group_by_player(self.read().extra_lives.clone(), |e| e.player_uuid))
+    }
+
+    async fn get_offenses(&self) -> Result<HashMap<Uuid, Vec<Offense>>, Error> {
+        Ok(group_by_player(self.read().offenses.clone(), |o| o.player_uuid))
+    }
+
+    async fn add_death(&self, death: &NewDeath) -> Result<Death, Error> {
+        let imported = death.imported;
+        let mut tables = self.write();
+        let death = Death {
+            id: tables.next_id(),
+            player_uuid: death.player_uuid,
+            stamp: death.stamp,
+            playtime: death.playtime,
+            reason: death.reason.clone(),
+        };
+        tables.deaths.push(death.clone());
+        drop(tables);
+        self.notify(ChangeKind::Deaths, death.player_uuid, Some(death.id), imported);
+        Ok(death)
+    }
+
+    async fn add_offense(&self, offense: &NewOffense) -> Result<Offense, Error> {
+        let imported = offense.imported;
+        let mut tables = self.write();
+        let offense = Offense {
+            id: tables.next_id(),
+            player_uuid: offense.player_uuid,
+            stamp: offense.stamp,
+            reason: offense.reason.clone(),
+            playtime: offense.playtime,
+        };
+        tables.offenses.push(offense.clone());
+        drop(tables);
+        self.notify(ChangeKind::Offenses, offense.player_uuid, Some(offense.id), imported);
+        Ok(offense)
+    }
+
+    async fn add_extra_life(&self, extra_life: &NewExtraLife) -> Result<ExtraLife, Error> {
+        let imported = extra_life.imported;
+        let mut tables = self.write();
+        let extra_life = ExtraLife {
+            id: tables.next_id(),
+            player_uuid: extra_life.player_uuid,
+            stamp: extra_life.stamp,
+            reason: extra_life.reason.clone(),
+            playtime: extra_life.playtime,
+        };
+        tables.extra_lives.push(extra_life.clone());
+        drop(tables);
+        self.notify(ChangeKind::ExtraLives, extra_life.player_uuid, Some(extra_life.id), imported);
+        Ok(extra_life)
+    }
+
+    async fn upsert_player(&self, player_uuid: &Uuid, player: &PlayerUpdate) -> Result<Player, Error> {
+        let imported = player.imported;
+        let mut tables = self.write();
+        let player = Player {
+            player_uuid: *player_uuid,
+            name: player.name.clone(),
+            joined: player.joined,
+            playtime: player.playtime,
+        };
+        match tables.players.iter_mut().find(|p| p.player_uuid == *player_uuid) {
+            Some(existing) => *existing = player.clone(),
+            None => {
+                tables.players.push(player.clone());
+                drop(tables);
+                self.notify(ChangeKind::Players, player.player_uuid, None, imported);
+            },
+        }
+        Ok(player)
+    }
+
+    async fn get_seasons(&self) -> Result<Vec<Season>, Error> {
+        Ok(self.read().seasons.clone())
+    }
+
+    async fn get_season(&self, name: &str) -> Result<Option<Season>, Error> {
+        Ok(self.read().seasons.iter().find(|s| s.name == name).cloned())
+    }
+
+    async fn add_season(&self, season: &NewSeason) -> Result<Season, Error> {
+        let mut tables = self.write();
+        if tables.seasons.iter().any(|s| s.name == season.name) {
+            return Err(Error::Conflict(format!("season {} already exists", season.name)));
+        }
+        let season = Season {
+            id: tables.next_id(),
+            name: season.name.clone(),
+            starts: season.starts,
+            ends: season.ends,
+            rules: season.rules.clone(),
+        };
+        tables.seasons.push(season.clone());
+        Ok(season)
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        Ok(self.read().api_keys.iter()
+            .find(|k| !k.revoked && k.key_hash == key_hash)
+            .map(|k| k.api_key.clone()))
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, Error> {
+        Ok(self.read().api_keys.iter()
+            .filter(|k| !k.revoked)
+            .map(|k| k.api_key.clone())
+            .collect())
+    }
+
+    async fn add_api_key(&self, name: &str, key_hash: &str, scopes: &[Scope]) -> Result<ApiKey, Error> {
+        let mut tables = self.write();
+        if tables.api_keys.iter().any(|k| k.key_hash == key_hash) {
+            return Err(Error::Conflict("api key already exists".to_string()));
+        }
+        let api_key = ApiKey {
+            id: tables.next_id(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            created: Utc::now(),
+        };
+        tables.api_keys.push(ApiKeyRow {
+            api_key: api_key.clone(),
+            key_hash: key_hash.to_string(),
+            revoked: false,
+        });
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<bool, Error> {
+        let mut tables = self.write();
+        match tables.api_keys.iter_mut().find(|k| k.api_key.id == id && !k.revoked) {
+            Some(row) => {
+                row.revoked = true;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn get_webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        Ok(self.read().webhooks.clone())
+    }
+
+    async fn add_webhook(&self, webhook: &NewWebhook, secret: &str) -> Result<Webhook, Error> {
+        let mut tables = self.write();
+        let webhook = Webhook {
+            id: tables.next_id(),
+            url: webhook.url.clone(),
+            secret: secret.to_string(),
+            event_types: webhook.event_types.clone(),
+            created: Utc::now(),
+        };
+        tables.webhooks.push(webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<bool, Error> {
+        let mut tables = self.write();
+        let before = tables.webhooks.len();
+        tables.webhooks.retain(|w| w.id != id);
+        tables.dead_letters.retain(|d| d.webhook_id != id);
+        Ok(tables.webhooks.len() < before)
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        Ok(self.read().dead_letters.clone())
+    }
+
+    async fn add_dead_letter(&self, webhook_id: i64, payload: &str, error: &str, attempts: i32) -> Result<DeadLetter, Error> {
+        let mut tables = self.write();
+        let dead_letter = DeadLetter {
+            id: tables.next_id(),
+            webhook_id,
+            payload: payload.to_string(),
+            error: error.to_string(),
+            attempts,
+            created: Utc::now(),
+        };
+        tables.dead_letters.push(dead_letter.clone());
+        Ok(dead_letter)
+    }
+
+    async fn save_projection(&self, player: &Player, timeline: &Timeline) -> Result<(), Error> {
+        self.write().projections.insert(player.player_uuid, LeaderboardRow::from_timeline(player, timeline));
+        Ok(())
+    }
+
+    async fn get_projections(&self, page: &PageQuery) -> Result<Vec<LeaderboardRow>, Error> {
+        Ok(leaderboard::page(self.read().projections.values().cloned().collect(), page))
+    }
+
+    async fn watch(&self) -> Result<broadcast::Receiver<Change>, Error> {
+        Ok(self.changes.subscribe())
+    }
+}
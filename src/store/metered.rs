@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Instant;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::auth::{ApiKey, Scope};
+use crate::errors::Error;
+use crate::leaderboard::{LeaderboardRow, PageQuery};
+use crate::metrics;
+use crate::store::{Change, DynStore, PoolUsage, Readiness, Store};
+use crate::timeline::Timeline;
+use crate::types::death::{Death, NewDeath};
+use crate::types::extra_life::{ExtraLife, NewExtraLife};
+use crate::types::offense::{NewOffense, Offense};
+use crate::types::player::{Player, PlayerUpdate};
+use crate::types::season::{NewSeason, Season};
+use crate::types::webhook::{DeadLetter, NewWebhook, Webhook};
+
+/// Wraps another store and records how long each of its methods takes for `/metrics`.
+#[derive(Debug, Clone)]
+pub struct MeteredStore {
+    inner: DynStore,
+}
+
+impl MeteredStore {
+    pub fn new(inner: DynStore) -> Self {
+        MeteredStore { inner }
+    }
+}
+
+async fn timed<T>(method: &'static str, call: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let result = call.await;
+    metrics::global().observe_store(method, started.elapsed());
+    result
+}
+
+#[async_trait]
+impl Store for MeteredStore {
+    async fn run_migrations(&self) -> Result<(), Error> {
+        timed("run_migrations", self.inner.run_migrations()).await
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        self.inner.pool_usage()
+    }
+
+    async fn readiness(&self) -> Readiness {
+        timed("readiness", self.inner.readiness()).await
+    }
+
+    async fn get_players(&self) -> Result<Vec<Player>, Error> {
+        timed("get_players", self.inner.get_players()).await
+    }
+
+    async fn get_player(&self, player_uuid: &Uuid) -> Result<Option<Player>, Error> {
+        timed("get_player", self.inner.get_player(player_uuid)).await
+    }
+
+    async fn get_player_by_name(&self, name: &str) -> Result<Option<Player>, Error> {
+        timed("get_player_by_name", self.inner.get_player_by_name(name)).await
+    }
+
+    async fn get_player_deaths(&self, player_uuid: &Uuid) -> Result<Vec<Death>, Error> {
+        timed("get_player_deaths", self.inner.get_player_deaths(player_uuid)).await
+    }
+
+    async fn get_player_extra_lives(&self, player_uuid: &Uuid) -> Result<Vec<ExtraLife>, Error> {
+        timed("get_player_extra_lives", self.inner.get_player_extra_lives(player_uuid)).await
+    }
+
+    async fn get_player_offenses(&self, player_uuid: &Uuid) -> Result<Vec<Offense>, Error> {
+        timed("get_player_offenses", self.inner.get_player_offenses(player_uuid)).await
+    }
+
+    async fn get_deaths(&self) -> Result<HashMap<Uuid, Vec<Death>>, Error> {
+        timed("get_deaths", self.inner.get_deaths()).await
+    }
+
+    async fn get_extra_lives(&self) -> Result<HashMap<Uuid, Vec<ExtraLife>>, Error> {
+        timed("get_extra_lives", self.inner.get_extra_lives()).await
+    }
+
+    async fn get_offenses(&self) -> Result<HashMap<Uuid, Vec<Offense>>, Error> {
+        timed("get_offenses", self.inner.get_offenses()).await
+    }
+
+    async fn add_death(&self, death: &NewDeath) -> Result<Death, Error> {
+        timed("add_death", self.inner.add_death(death)).await
+    }
+
+    async fn add_offense(&self, offense: &NewOffense) -> Result<Offense, Error> {
+        timed("add_offense", self.inner.add_offense(offense)).await
+    }
+
+    async fn add_extra_life(&self, extra_life: &NewExtraLife) -> Result<ExtraLife, Error> {
+        timed("add_extra_life", self.inner.add_extra_life(extra_life)).await
+    }
+
+    async fn upsert_player(&self, player_uuid: &Uuid, player: &PlayerUpdate) -> Result<Player, Error> {
+        timed("upsert_player", self.inner.upsert_player(player_uuid, player)).await
+    }
+
+    async fn get_seasons(&self) -> Result<Vec<Season>, Error> {
+        timed("get_seasons", self.inner.get_seasons()).await
+    }
+
+    async fn get_season(&self, name: &str) -> Result<Option<Season>, Error> {
+        timed("get_season", self.inner.get_season(name)).await
+    }
+
+    async fn add_season(&self, season: &NewSeason) -> Result<Season, Error> {
+        timed("add_season", self.inner.add_season(season)).await
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        timed("get_api_key", self.inner.get_api_key(key_hash)).await
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, Error> {
+        timed("get_api_keys", self.inner.get_api_keys()).await
+    }
+
+    async fn add_api_key(&self, name: &str, key_hash: &str, scopes: &[Scope]) -> Result<ApiKey, Error> {
+        timed("add_api_key", self.inner.add_api_key(name, key_hash, scopes)).await
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<bool, Error> {
+        timed("revoke_api_key", self.inner.revoke_api_key(id)).await
+    }
+
+    async fn get_webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        timed("get_webhooks", self.inner.get_webhooks()).await
+    }
+
+    async fn add_webhook(&self, webhook: &NewWebhook, secret: &str) -> Result<Webhook, Error> {
+        timed("add_webhook", self.inner.add_webhook(webhook, secret)).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<bool, Error> {
+        timed("delete_webhook", self.inner.delete_webhook(id)).await
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        timed("get_dead_letters", self.inner.get_dead_letters()).await
+    }
+
+    async fn add_dead_letter(&self, webhook_id: i64, payload: &str, error: &str, attempts: i32) -> Result<DeadLetter, Error> {
+        timed("add_dead_letter", self.inner.add_dead_letter(webhook_id, payload, error, attempts)).await
+    }
+
+    async fn save_projection(&self, player: &Player, timeline: &Timeline) -> Result<(), Error> {
+        timed("save_projection", self.inner.save_projection(player, timeline)).await
+    }
+
+    async fn get_projections(&self, page: &PageQuery) -> Result<Vec<LeaderboardRow>, Error> {
+        timed("get_projections", self.inner.get_projections(page)).await
+    }
+
+    /// Not timed, the subscription lasts as long as the watcher.
+    async fn watch(&self) -> Result<broadcast::Receiver<Change>, Error> {
+        self.inner.watch().await
+    }
+}
@@ -1,143 +1,143 @@
-use chrono::{DateTime, Utc};
-use sqlx::{PgPool, Row};
-use sqlx::postgres::{PgPoolOptions, PgRow};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use uuid::Uuid;
+use crate::auth::{ApiKey, Scope};
 use crate::errors::Error;
-use crate::types::death::Death;
-use crate::types::extra_life::ExtraLife;
-use crate::types::offense::Offense;
-use crate::types::player::Player;
-
-#[derive(Clone, Debug)]
-pub struct Store {
-    connection: PgPool,
+use crate::leaderboard::{LeaderboardRow, PageQuery};
+use crate::timeline::Timeline;
+use crate::types::death::{Death, NewDeath};
+use crate::types::extra_life::{ExtraLife, NewExtraLife};
+use crate::types::offense::{NewOffense, Offense};
+use crate::types::player::{Player, PlayerUpdate};
+use crate::types::season::{NewSeason, Season};
+use crate::types::webhook::{DeadLetter, NewWebhook, Webhook};
+
+pub mod memory;
+pub mod metered;
+pub mod postgres;
+
+/// A row that was added, sent to everything watching the store.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub player_uuid: Uuid,
+    /// The id of the row, players don't have one.
+    pub id: Option<i64>,
+    /// The row was written by an import, it isn't a live event and the import re-projects
+    /// once it's done.
+    #[serde(default)]
+    pub imported: bool,
 }
 
-impl Store {
-    pub async fn new(db_url: &str) -> Self {
-        let db_pool = match PgPoolOptions::new()
-            .max_connections(5)
-            .connect(db_url).await {
-            Ok(pool) => pool,
-            Err(e) => panic!("Couldn't establish DB connection:{}", e),
-        };
-
-        Store {
-            connection: db_pool,
-        }
-    }
+/// The table the row was added to.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Players,
+    Deaths,
+    ExtraLives,
+    Offenses,
+}
 
-    pub async fn get_players(&self) -> Result<Vec<Player>, Error> {
-        match sqlx::query("SELECT player_uuid, name, joined, playtime FROM players")
-            .map(|row: PgRow| {
-                let u : uuid::Uuid = row.get(0);
-                //let unix_timestamp: i32 = row.get(2);
-                //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
-                let t: DateTime<Utc> = row.get(2);
-
-                Player {
-                    player_uuid: u.to_string(),
-                    name: row.get(1),
-                    joined: t,
-                    playtime: row.get(3),
-                }
-            })
-            .fetch_all(&self.connection)
-            .await
-        {
-            Ok(players) => Ok(players),
-            Err(e) => {
-                // todo -p print
-                println!("{}", e);
-                Err(Error::DatabaseQueryError)
-            },
-        }
-    }
+/// The connections of a store's pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolUsage {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
 
-    pub async fn get_player_deaths(&self, player_uuid: &String) -> Result<Vec<Death>, Error> {
-        match sqlx::query("SELECT id, player_uuid, stamp, playtime, reason FROM deaths WHERE player_uuid = $1")
-            .bind(Uuid::parse_str(player_uuid).unwrap())
-            .map(|row: PgRow| {
-                let u : uuid::Uuid = row.get(1);
-                //let unix_timestamp: i32 = row.get(2);
-                //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
-                let t: DateTime<Utc> = row.get(2);
-
-                Death {
-                    id: row.get(0),
-                    player_uuid: u.to_string(),
-                    stamp: t,
-                    playtime: row.get(3),
-                    reason: row.get(4),
-                }
-            })
-            .fetch_all(&self.connection)
-            .await
-        {
-            Ok(deaths) => Ok(deaths),
-            Err(e) => {
-                // todo -p print
-                println!("{}", e);
-                Err(Error::DatabaseQueryError)
-            },
-        }
-    }
+/// Whether the store can serve requests, for `/readyz`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Readiness {
+    pub database: bool,
+    /// Versions of the migrations built into the binary that haven't been applied.
+    pub pending_migrations: Vec<i64>,
+    pub missing_tables: Vec<String>,
+    /// Why the database couldn't be checked.
+    pub error: Option<String>,
+}
 
-    pub async fn get_player_extra_lives(&self, player_uuid: &String) -> Result<Vec<ExtraLife>, Error> {
-        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM extra_lives WHERE player_uuid = $1")
-            .bind(Uuid::parse_str(player_uuid).unwrap())
-            .map(|row: PgRow| {
-                let u : uuid::Uuid = row.get(1);
-                //let unix_timestamp: i32 = row.get(2);
-                //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
-                let t: DateTime<Utc> = row.get(2);
-
-                ExtraLife {
-                    id: row.get(0),
-                    player_uuid: u.to_string(),
-                    stamp: t,
-                    reason: row.get(3),
-                    playtime: row.get(4),
-                }
-            })
-            .fetch_all(&self.connection)
-            .await
-        {
-            Ok(extra_lives) => Ok(extra_lives),
-            Err(e) => {
-                // todo -p print
-                println!("{}", e);
-                Err(Error::DatabaseQueryError)
-            },
-        }
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.database && self.error.is_none() && self.pending_migrations.is_empty() && self.missing_tables.is_empty()
     }
+}
+
+/// The store shared by the routes, cloning it is cheap.
+pub type DynStore = Arc<dyn Store>;
+
+/// Everything the api reads and writes. Postgres is the real backend, the in-memory
+/// backend is for tests and demos.
+#[async_trait]
+pub trait Store: Send + Sync + Debug {
+    /// Creates or upgrades the tables.
+    async fn run_migrations(&self) -> Result<(), Error>;
+    /// Empty for stores without a connection pool.
+    fn pool_usage(&self) -> Option<PoolUsage>;
+    /// Checks the database can be reached and has every table, never fails itself.
+    async fn readiness(&self) -> Readiness;
 
-    pub async fn get_player_offenses(&self, player_uuid: &String) -> Result<Vec<Offense>, Error> {
-        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM offenses WHERE player_uuid = $1")
-            .bind(Uuid::parse_str(player_uuid).unwrap())
-            .map(|row: PgRow| {
-                let u : uuid::Uuid = row.get(1);
-                //let unix_timestamp: i32 = row.get(2);
-                //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
-                let t: DateTime<Utc> = row.get(2);
-
-                Offense {
-                    id: row.get(0),
-                    player_uuid: u.to_string(),
-                    stamp: t,
-                    reason: row.get(3),
-                    playtime: row.get(4),
-                }
-            })
-            .fetch_all(&self.connection)
-            .await
-        {
-            Ok(offenses) => Ok(offenses),
-            Err(e) => {
-                // todo -p print
-                println!("{}", e);
-                Err(Error::DatabaseQueryError)
-            },
-        }
+    async fn get_players(&self) -> Result<Vec<Player>, Error>;
+    async fn get_player(&self, player_uuid: &Uuid) -> Result<Option<Player>, Error>;
+    /// Minecraft names are unique ignoring case, so the lookup is case-insensitive.
+    async fn get_player_by_name(&self, name: &str) -> Result<Option<Player>, Error>;
+    async fn get_player_deaths(&self, player_uuid: &Uuid) -> Result<Vec<Death>, Error>;
+    async fn get_player_extra_lives(&self, player_uuid: &Uuid) -> Result<Vec<ExtraLife>, Error>;
+    async fn get_player_offenses(&self, player_uuid: &Uuid) -> Result<Vec<Offense>, Error>;
+
+    /// Loads every death at once, grouped by player uuid.
+    async fn get_deaths(&self) -> Result<HashMap<Uuid, Vec<Death>>, Error>;
+    /// Loads every extra life at once, grouped by player uuid.
+    async fn get_extra_lives(&self) -> Result<HashMap<Uuid, Vec<ExtraLife>>, Error>;
+    /// Loads every offense at once, grouped by player uuid.
+    async fn get_offenses(&self) -> Result<HashMap<Uuid, Vec<Offense>>, Error>;
+
+    async fn add_death(&self, death: &NewDeath) -> Result<Death, Error>;
+    async fn add_offense(&self, offense: &NewOffense) -> Result<Offense, Error>;
+    async fn add_extra_life(&self, extra_life: &NewExtraLife) -> Result<ExtraLife, Error>;
+    /// Creates the player, or updates their name, joined and playtime when they already exist.
+    async fn upsert_player(&self, player_uuid: &Uuid, player: &PlayerUpdate) -> Result<Player, Error>;
+
+    async fn get_seasons(&self) -> Result<Vec<Season>, Error>;
+    async fn get_season(&self, name: &str) -> Result<Option<Season>, Error>;
+    async fn add_season(&self, season: &NewSeason) -> Result<Season, Error>;
+
+    /// Looks up a key that hasn't been revoked by the hash of the key.
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error>;
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, Error>;
+    async fn add_api_key(&self, name: &str, key_hash: &str, scopes: &[Scope]) -> Result<ApiKey, Error>;
+    /// Revokes the key, returns false when there was no key to revoke.
+    async fn revoke_api_key(&self, id: i64) -> Result<bool, Error>;
+
+    async fn get_webhooks(&self) -> Result<Vec<Webhook>, Error>;
+    async fn add_webhook(&self, webhook: &NewWebhook, secret: &str) -> Result<Webhook, Error>;
+    /// Deletes the webhook and its dead letters, returns false when there was no webhook to delete.
+    async fn delete_webhook(&self, id: i64) -> Result<bool, Error>;
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>, Error>;
+    async fn add_dead_letter(&self, webhook_id: i64, payload: &str, error: &str, attempts: i32) -> Result<DeadLetter, Error>;
+
+    /// Replaces the player's projected stats and events with the timeline.
+    async fn save_projection(&self, player: &Player, timeline: &Timeline) -> Result<(), Error>;
+    /// A page of the players' projected stats as leaderboard rows, the ranks aren't set.
+    async fn get_projections(&self, page: &PageQuery) -> Result<Vec<LeaderboardRow>, Error>;
+
+    /// Subscribes to new players, deaths, extra lives and offenses. Rows that are
+    /// updated instead of added are not sent.
+    async fn watch(&self) -> Result<broadcast::Receiver<Change>, Error>;
+}
+
+/// How many changes a slow watcher can fall behind before it starts missing them.
+pub(crate) const CHANGE_BUFFER: usize = 256;
+
+pub(crate) fn group_by_player<T>(rows: Vec<T>, key: impl Fn(&T) -> Uuid) -> HashMap<Uuid, Vec<T>> {
+    let mut grouped: HashMap<Uuid, Vec<T>> = HashMap::new();
+    for row in rows {
+        grouped.entry(key(&row)).or_default().push(row);
     }
-}
\ No newline at end of file
+    grouped
+}
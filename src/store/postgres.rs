@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Row};
+use sqlx::postgres::{PgListener, PgPoolOptions, PgRow};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+use uuid::Uuid;
+use crate::auth::{ApiKey, Scope};
+use crate::config::env_or;
+use crate::errors::Error;
+use crate::leaderboard::{LeaderboardRow, PageQuery, SortBy, SortOrder, StateFilter};
+use crate::timeline::{PlayerState, Timeline};
+use crate::types::death::{Death, NewDeath};
+use crate::types::extra_life::{ExtraLife, NewExtraLife};
+use crate::types::offense::{NewOffense, Offense};
+use crate::types::player::{Player, PlayerUpdate};
+use crate::types::season::{NewSeason, Season};
+use crate::types::webhook::{DeadLetter, NewWebhook, Webhook};
+use crate::store::{group_by_player, Change, PoolUsage, Readiness, Store, CHANGE_BUFFER};
+
+/// The NOTIFY channel the triggers in the migrations send changes on.
+const CHANGE_CHANNEL: &str = "hardcore_changes";
+
+/// The tables the api reads or writes, `/readyz` fails when any of them is missing.
+const REQUIRED_TABLES: &[&str] = &[
+    "players", "deaths", "extra_lives", "offenses", "api_keys", "webhooks", "webhook_dead_letters", "seasons",
+    "player_timeline_stats", "timeline_events",
+];
+
+/// The longest wait between connection attempts.
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectConfig {
+    /// How many times connecting is tried before giving up.
+    pub max_attempts: u32,
+    /// The wait before the second attempt, it doubles after every attempt.
+    pub backoff: Duration,
+}
+
+impl ConnectConfig {
+    /// Loads the config from env variables, DB_CONNECT_ATTEMPTS and DB_CONNECT_BACKOFF_SECONDS.
+    pub fn from_env() -> Self {
+        ConnectConfig {
+            max_attempts: env_or("DB_CONNECT_ATTEMPTS", 10),
+            backoff: Duration::from_secs(env_or("DB_CONNECT_BACKOFF_SECONDS", 1)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PgStore {
+    connection: PgPool,
+}
+
+impl PgStore {
+    /// Retries while postgres is still starting, e.g. under `docker compose up`.
+    pub async fn new(db_url: &str, retry: ConnectConfig) -> Result<Self, Error> {
+        let mut backoff = retry.backoff;
+        let mut attempt = 1;
+        loop {
+            match PgPoolOptions::new()
+                .max_connections(5)
+                .connect(db_url).await {
+                Ok(pool) => return Ok(PgStore { connection: pool }),
+                Err(e) if attempt < retry.max_attempts => {
+                    event!(target: "hardcore-api", Level::WARN, "couldn't connect to the database (attempt {} of {}), retrying in {:?}: {}",
+                        attempt, retry.max_attempts, backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                    attempt += 1;
+                },
+                Err(e) => return Err(Error::Internal(format!("couldn't connect to the database after {} attempts: {}", attempt, e))),
+            }
+        }
+    }
+
+    fn player_from_row(row: PgRow) -> Player {
+        let u : uuid::Uuid = row.get(0);
+        //let unix_timestamp: i32 = row.get(2);
+        //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
+        let t: DateTime<Utc> = row.get(2);
+
+        Player {
+            player_uuid: u,
+            name: row.get(1),
+            joined: t,
+            playtime: row.get(3),
+        }
+    }
+
+    fn death_from_row(row: PgRow) -> Death {
+        let u : uuid::Uuid = row.get(1);
+        //let unix_timestamp: i32 = row.get(2);
+        //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
+        let t: DateTime<Utc> = row.get(2);
+
+        Death {
+            id: row.get(0),
+            player_uuid: u,
+            stamp: t,
+            playtime: row.get(3),
+            reason: row.get(4),
+        }
+    }
+
+    fn extra_life_from_row(row: PgRow) -> ExtraLife {
+        let u : uuid::Uuid = row.get(1);
+        //let unix_timestamp: i32 = row.get(2);
+        //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
+        let t: DateTime<Utc> = row.get(2);
+
+        ExtraLife {
+            id: row.get(0),
+            player_uuid: u,
+            stamp: t,
+            reason: row.get(3),
+            playtime: row.get(4),
+        }
+    }
+
+    fn offense_from_row(row: PgRow) -> Offense {
+        let u : uuid::Uuid = row.get(1);
+        //let unix_timestamp: i32 = row.get(2);
+        //let t = DateTime::<Utc>::from_timestamp(unix_timestamp as i64, 0).unwrap();
+        let t: DateTime<Utc> = row.get(2);
+
+        Offense {
+            id: row.get(0),
+            player_uuid: u,
+            stamp: t,
+            reason: row.get(3),
+            playtime: row.get(4),
+        }
+    }
+
+    fn api_key_from_row(row: PgRow) -> ApiKey {
+        let scopes: Vec<String> = row.get(2);
+
+        ApiKey {
+            id: row.get(0),
+            name: row.get(1),
+            // unknown scopes are ignored so an old build can't be given new permissions.
+            scopes: scopes.iter().filter_map(|s| s.parse().ok()).collect(),
+            created: row.get(3),
+        }
+    }
+
+    fn season_from_row(row: PgRow) -> Season {
+        let rules: Option<String> = row.get(4);
+
+        Season {
+            id: row.get(0),
+            name: row.get(1),
+            starts: row.get(2),
+            ends: row.get(3),
+            // rules that can't be read fall back to the server's rules instead of failing the request.
+            rules: rules.and_then(|r| serde_json::from_str(&r).ok()),
+        }
+    }
+
+    fn webhook_from_row(row: PgRow) -> Webhook {
+        let event_types: Vec<String> = row.get(3);
+
+        Webhook {
+            id: row.get(0),
+            url: row.get(1),
+            secret: row.get(2),
+            event_types: event_types.iter().filter_map(|e| e.parse().ok()).collect(),
+            created: row.get(4),
+        }
+    }
+
+    fn projection_from_row(row: PgRow) -> LeaderboardRow {
+        let player_state: String = row.get(2);
+        let deaths: i32 = row.get(6);
+        let offenses: i32 = row.get(7);
+
+        LeaderboardRow {
+            rank: 0,
+            player_uuid: row.get(0),
+            player_name: row.get(1),
+            // a state from a newer build is out of lives, the same as the states it could be.
+            player_state: player_state.parse().unwrap_or(PlayerState::Dead),
+            survived_seconds: row.get(3),
+            longest_life_seconds: row.get(4),
+            shortest_life_seconds: row.get(5),
+            deaths: deaths as usize,
+            offenses: offenses as usize,
+        }
+    }
+
+    fn dead_letter_from_row(row: PgRow) -> DeadLetter {
+        DeadLetter {
+            id: row.get(0),
+            webhook_id: row.get(1),
+            payload: row.get(2),
+            error: row.get(3),
+            attempts: row.get(4),
+            created: row.get(5),
+        }
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    /// Creates or upgrades the tables, the migrations are embedded from the migrations folder.
+    async fn run_migrations(&self) -> Result<(), Error> {
+        match sqlx::migrate!().run(&self.connection).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::Internal(format!("migration failed: {}", e))),
+        }
+    }
+
+    async fn readiness(&self) -> Readiness {
+        if let Err(e) = sqlx::query("SELECT 1").execute(&self.connection).await {
+            return Readiness { error: Some(e.to_string()), ..Readiness::default() };
+        }
+
+        // the migrations table isn't there until the first migration has run, so nothing is applied.
+        let applied: Vec<i64> = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success")
+            .map(|row: PgRow| row.get(0))
+            .fetch_all(&self.connection)
+            .await
+            .unwrap_or_default();
+        let pending_migrations = sqlx::migrate!().iter()
+            .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+            .map(|m| m.version)
+            .collect();
+
+        let tables: Vec<String> = match sqlx::query("SELECT table_name::text FROM information_schema.tables
+                WHERE table_schema = current_schema() AND table_name = ANY($1)")
+            .bind(REQUIRED_TABLES)
+            .map(|row: PgRow| row.get(0))
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => return Readiness { database: true, pending_migrations, error: Some(e.to_string()), ..Readiness::default() },
+        };
+        let missing_tables = REQUIRED_TABLES.iter()
+            .filter(|table| !tables.iter().any(|t| t == *table))
+            .map(|table| table.to_string())
+            .collect();
+
+        Readiness { database: true, pending_migrations, missing_tables, error: None }
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        Some(PoolUsage {
+            size: self.connection.size(),
+            idle: self.connection.num_idle(),
+            max: self.connection.options().get_max_connections(),
+        })
+    }
+
+    async fn get_players(&self) -> Result<Vec<Player>, Error> {
+        match sqlx::query("SELECT player_uuid, name, joined, playtime FROM players")
+            .map(Self::player_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(players) => Ok(players),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_player(&self, player_uuid: &Uuid) -> Result<Option<Player>, Error> {
+        match sqlx::query("SELECT player_uuid, name, joined, playtime FROM players WHERE player_uuid = $1")
+            .bind(player_uuid)
+            .map(Self::player_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(player) => Ok(player),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_player_by_name(&self, name: &str) -> Result<Option<Player>, Error> {
+        match sqlx::query("SELECT player_uuid, name, joined, playtime FROM players WHERE lower(name) = lower($1)")
+            .bind(name)
+            .map(Self::player_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(player) => Ok(player),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_player_deaths(&self, player_uuid: &Uuid) -> Result<Vec<Death>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, playtime, reason FROM deaths WHERE player_uuid = $1")
+            .bind(player_uuid)
+            .map(Self::death_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(deaths) => Ok(deaths),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_player_extra_lives(&self, player_uuid: &Uuid) -> Result<Vec<ExtraLife>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM extra_lives WHERE player_uuid = $1")
+            .bind(player_uuid)
+            .map(Self::extra_life_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(extra_lives) => Ok(extra_lives),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_player_offenses(&self, player_uuid: &Uuid) -> Result<Vec<Offense>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM offenses WHERE player_uuid = $1")
+            .bind(player_uuid)
+            .map(Self::offense_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(offenses) => Ok(offenses),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_deaths(&self) -> Result<HashMap<Uuid, Vec<Death>>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, playtime, reason FROM deaths ORDER BY player_uuid")
+            .map(Self::death_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(deaths) => Ok(group_by_player(deaths, |d| d.player_uuid)),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_extra_lives(&self) -> Result<HashMap<Uuid, Vec<ExtraLife>>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM extra_lives ORDER BY player_uuid")
+            .map(Self::extra_life_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(extra_lives) => Ok(group_by_player(extra_lives, |e| e.player_uuid)),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_offenses(&self) -> Result<HashMap<Uuid, Vec<Offense>>, Error> {
+        match sqlx::query("SELECT id, player_uuid, stamp, reason, playtime FROM offenses ORDER BY player_uuid")
+            .map(Self::offense_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(offenses) => Ok(group_by_player(offenses, |o| o.player_uuid)),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_death(&self, death: &NewDeath) -> Result<Death, Error> {
+        match sqlx::query("INSERT INTO deaths (player_uuid, stamp, playtime, reason, imported) VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, player_uuid, stamp, playtime, reason")
+            .bind(death.player_uuid)
+            .bind(death.stamp)
+            .bind(death.playtime)
+            .bind(&death.reason)
+            .bind(death.imported)
+            .map(Self::death_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(death) => Ok(death),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_offense(&self, offense: &NewOffense) -> Result<Offense, Error> {
+        match sqlx::query("INSERT INTO offenses (player_uuid, stamp, reason, playtime, imported) VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, player_uuid, stamp, reason, playtime")
+            .bind(offense.player_uuid)
+            .bind(offense.stamp)
+            .bind(&offense.reason)
+            .bind(offense.playtime)
+            .bind(offense.imported)
+            .map(Self::offense_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(offense) => Ok(offense),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_extra_life(&self, extra_life: &NewExtraLife) -> Result<ExtraLife, Error> {
+        match sqlx::query("INSERT INTO extra_lives (player_uuid, stamp, reason, playtime, imported) VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, player_uuid, stamp, reason, playtime")
+            .bind(extra_life.player_uuid)
+            .bind(extra_life.stamp)
+            .bind(&extra_life.reason)
+            .bind(extra_life.playtime)
+            .bind(extra_life.imported)
+            .map(Self::extra_life_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(extra_life) => Ok(extra_life),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn upsert_player(&self, player_uuid: &Uuid, player: &PlayerUpdate) -> Result<Player, Error> {
+        match sqlx::query("INSERT INTO players (player_uuid, name, joined, playtime, imported) VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (player_uuid) DO UPDATE SET name = $2, joined = $3, playtime = $4
+                RETURNING player_uuid, name, joined, playtime")
+            .bind(player_uuid)
+            .bind(&player.name)
+            .bind(player.joined)
+            .bind(player.playtime)
+            .bind(player.imported)
+            .map(Self::player_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(player) => Ok(player),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_seasons(&self) -> Result<Vec<Season>, Error> {
+        match sqlx::query("SELECT id, name, starts, ends, rules::text FROM seasons ORDER BY starts")
+            .map(Self::season_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(seasons) => Ok(seasons),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_season(&self, name: &str) -> Result<Option<Season>, Error> {
+        match sqlx::query("SELECT id, name, starts, ends, rules::text FROM seasons WHERE name = $1")
+            .bind(name)
+            .map(Self::season_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(season) => Ok(season),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_season(&self, season: &NewSeason) -> Result<Season, Error> {
+        let rules = match &season.rules {
+            Some(rules) => match serde_json::to_string(rules) {
+                Ok(res) => Some(res),
+                Err(e) => return Err(Error::Internal(format!("couldn't encode rules: {}", e))),
+            },
+            None => None,
+        };
+        match sqlx::query("INSERT INTO seasons (name, starts, ends, rules) VALUES ($1, $2, $3, $4::jsonb)
+                RETURNING id, name, starts, ends, rules::text")
+            .bind(&season.name)
+            .bind(season.starts)
+            .bind(season.ends)
+            .bind(rules)
+            .map(Self::season_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(season) => Ok(season),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        match sqlx::query("SELECT id, name, scopes, created FROM api_keys WHERE key_hash = $1 AND NOT revoked")
+            .bind(key_hash)
+            .map(Self::api_key_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(api_key) => Ok(api_key),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, Error> {
+        match sqlx::query("SELECT id, name, scopes, created FROM api_keys WHERE NOT revoked ORDER BY id")
+            .map(Self::api_key_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(api_keys) => Ok(api_keys),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_api_key(&self, name: &str, key_hash: &str, scopes: &[Scope]) -> Result<ApiKey, Error> {
+        let scopes: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        match sqlx::query("INSERT INTO api_keys (name, key_hash, scopes) VALUES ($1, $2, $3)
+                RETURNING id, name, scopes, created")
+            .bind(name)
+            .bind(key_hash)
+            .bind(scopes)
+            .map(Self::api_key_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(api_key) => Ok(api_key),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<bool, Error> {
+        match sqlx::query("UPDATE api_keys SET revoked = true WHERE id = $1 AND NOT revoked")
+            .bind(id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        match sqlx::query("SELECT id, url, secret, event_types, created FROM webhooks ORDER BY id")
+            .map(Self::webhook_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(webhooks) => Ok(webhooks),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_webhook(&self, webhook: &NewWebhook, secret: &str) -> Result<Webhook, Error> {
+        let event_types: Vec<String> = webhook.event_types.iter().map(|e| e.to_string()).collect();
+        match sqlx::query("INSERT INTO webhooks (url, secret, event_types) VALUES ($1, $2, $3)
+                RETURNING id, url, secret, event_types, created")
+            .bind(&webhook.url)
+            .bind(secret)
+            .bind(event_types)
+            .map(Self::webhook_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(webhook) => Ok(webhook),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<bool, Error> {
+        match sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        match sqlx::query("SELECT id, webhook_id, payload, error, attempts, created FROM webhook_dead_letters ORDER BY id")
+            .map(Self::dead_letter_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(dead_letters) => Ok(dead_letters),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn add_dead_letter(&self, webhook_id: i64, payload: &str, error: &str, attempts: i32) -> Result<DeadLetter, Error> {
+        match sqlx::query("INSERT INTO webhook_dead_letters (webhook_id, payload, error, attempts) VALUES ($1, $2, $3, $4)
+                RETURNING id, webhook_id, payload, error, attempts, created")
+            .bind(webhook_id)
+            .bind(payload)
+            .bind(error)
+            .bind(attempts)
+            .map(Self::dead_letter_from_row)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(dead_letter) => Ok(dead_letter),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// The events are replaced in a transaction so nothing reading them sees half a timeline.
+    async fn save_projection(&self, player: &Player, timeline: &Timeline) -> Result<(), Error> {
+        let row = LeaderboardRow::from_timeline(player, timeline);
+        let mut tx = self.connection.begin().await?;
+
+        sqlx::query("DELETE FROM timeline_events WHERE player_uuid = $1")
+            .bind(player.player_uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        // one insert for the whole timeline, a column per array.
+        let events = &timeline.events;
+        sqlx::query("INSERT INTO timeline_events (player_uuid, position, event_id, stamp, what, context, playtime, span,
+                unranked, no_lives, ranked_lives_after, unranked_lives_after)
+                SELECT $1, * FROM UNNEST($2::integer[], $3::bigint[], $4::timestamptz[], $5::text[], $6::text[],
+                $7::integer[], $8::integer[], $9::boolean[], $10::boolean[], $11::integer[], $12::integer[])")
+            .bind(player.player_uuid)
+            .bind((0..events.len() as i32).collect::<Vec<i32>>())
+            .bind(events.iter().map(|e| e.id).collect::<Vec<Option<i64>>>())
+            .bind(events.iter().map(|e| e.stamp).collect::<Vec<_>>())
+            .bind(events.iter().map(|e| e.what.to_string()).collect::<Vec<String>>())
+            .bind(events.iter().map(|e| e.context.as_str()).collect::<Vec<&str>>())
+            .bind(events.iter().map(|e| e.playtime).collect::<Vec<i32>>())
+            .bind(events.iter().map(|e| e.span).collect::<Vec<i32>>())
+            .bind(events.iter().map(|e| e.unranked).collect::<Vec<bool>>())
+            .bind(events.iter().map(|e| e.no_lives).collect::<Vec<bool>>())
+            .bind(events.iter().map(|e| e.lives_after.ranked).collect::<Vec<i32>>())
+            .bind(events.iter().map(|e| e.lives_after.unranked).collect::<Vec<i32>>())
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("INSERT INTO player_timeline_stats (player_uuid, player_name, player_state, survived_seconds,
+                longest_life_seconds, shortest_life_seconds, ranked_lives_remaining, unranked_lives_remaining, deaths, offenses)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                ON CONFLICT (player_uuid) DO UPDATE SET player_name = $2, player_state = $3, survived_seconds = $4,
+                longest_life_seconds = $5, shortest_life_seconds = $6, ranked_lives_remaining = $7,
+                unranked_lives_remaining = $8, deaths = $9, offenses = $10, built = now()")
+            .bind(player.player_uuid)
+            .bind(&row.player_name)
+            .bind(format!("{:?}", row.player_state))
+            .bind(row.survived_seconds)
+            .bind(row.longest_life_seconds)
+            .bind(row.shortest_life_seconds)
+            .bind(timeline.ranked_lives_remaining)
+            .bind(timeline.unranked_lives_remaining)
+            .bind(row.deaths as i32)
+            .bind(row.offenses as i32)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Sorted, filtered and paged by postgres, the same order as `leaderboard::sort`. Names are
+    /// compared by their bytes, the same as rust compares them.
+    async fn get_projections(&self, page: &PageQuery) -> Result<Vec<LeaderboardRow>, Error> {
+        let column = match page.sort {
+            SortBy::Survived => "survived_seconds",
+            SortBy::LongestLife => "longest_life_seconds",
+            SortBy::ShortestLife => "shortest_life_seconds",
+            SortBy::Deaths => "deaths",
+            SortBy::Offenses => "offenses",
+        };
+        let (direction, past) = match page.order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        let state = match page.state {
+            None => "TRUE",
+            Some(StateFilter::Alive) => "player_state = 'Alive'",
+            Some(StateFilter::Dead) => "player_state <> 'Alive'",
+            Some(StateFilter::Ghost) => "player_state = 'Ghost'",
+        };
+        // without a cursor every row is after it.
+        let after = match page.after {
+            None => "TRUE".to_string(),
+            Some(_) => format!("({0} {1} $1 OR ({0} = $1 AND (lower(player_name) COLLATE \"C\", player_uuid) > ($2, $3)))", column, past),
+        };
+        let (value, name, player_uuid) = match &page.after {
+            Some(cursor) => (cursor.value, cursor.name.clone(), cursor.player_uuid),
+            None => (0, String::new(), Uuid::nil()),
+        };
+
+        let sql = format!("SELECT player_uuid, player_name, player_state, survived_seconds, longest_life_seconds,
+                shortest_life_seconds, deaths, offenses FROM player_timeline_stats WHERE {} AND {}
+                ORDER BY {} {}, lower(player_name) COLLATE \"C\", player_uuid LIMIT $4", state, after, column, direction);
+        match sqlx::query(&sql)
+            .bind(value)
+            .bind(name)
+            .bind(player_uuid)
+            .bind(page.limit as i64)
+            .map(Self::projection_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(rows) => Ok(rows),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    async fn watch(&self) -> Result<broadcast::Receiver<Change>, Error> {
+        let mut listener = PgListener::connect_with(&self.connection).await?;
+        listener.listen(CHANGE_CHANNEL).await?;
+
+        let (sender, receiver) = broadcast::channel(CHANGE_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                // the listener reconnects by itself, an error means the reconnect failed.
+                let notification = match listener.recv().await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        event!(target: "hardcore-api", Level::ERROR, "change listener failed: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    },
+                };
+
+                let change: Change = match serde_json::from_str(notification.payload()) {
+                    Ok(res) => res,
+                    Err(e) => {
+                        event!(target: "hardcore-api", Level::WARN, "bad change notification {}: {}", notification.payload(), e);
+                        continue;
+                    },
+                };
+
+                if sender.send(change).is_err() {
+                    // nothing is watching anymore.
+                    break;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
@@ -0,0 +1,37 @@
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+use crate::types::death::Death;
+use crate::types::extra_life::ExtraLife;
+use crate::types::player::Player;
+
+/// `seconds` after the test player joined, 2024-08-30.
+pub fn at(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_725_000_000 + seconds, 0).unwrap()
+}
+
+/// The player the tests start with, joined on 2024-08-30 with an hour of playtime.
+pub fn steve() -> Player {
+    Player {
+        player_uuid: Uuid::from_u128(1),
+        name: "Steve".to_string(),
+        joined: at(0),
+        playtime: 72_000,
+    }
+}
+
+pub fn player() -> Player {
+    Player {
+        player_uuid: Uuid::nil(),
+        name: "Steve".to_string(),
+        joined: at(0),
+        playtime: 100_000,
+    }
+}
+
+pub fn death(id: i64, stamp: DateTime<Utc>, playtime: i32) -> Death {
+    Death { id, player_uuid: Uuid::nil(), stamp, playtime, reason: format!("death {}", id) }
+}
+
+pub fn extra_life(id: i64, stamp: DateTime<Utc>, playtime: i32) -> ExtraLife {
+    ExtraLife { id, player_uuid: Uuid::nil(), stamp, playtime, reason: "VOTE".to_string() }
+}
@@ -1,29 +1,61 @@
 use std::cmp::Ordering;
 use std::option::Option;
+use std::time::Instant;
 use chrono::Utc;
-use serde::Serialize;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use crate::errors::Error;
-use crate::store::Store;
+use crate::store::{DynStore, Store};
 use crate::types::death::Death;
 use crate::types::extra_life::ExtraLife;
 use crate::types::offense::Offense;
 use crate::types::player::Player;
+use crate::types::season::Season;
 use crate::date_format;
+use crate::metrics;
+use crate::rules::{GhostBehaviour, RuleSet};
 use tracing::{event, Level};
 
-#[derive(Serialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
 pub struct Timeline {
     pub player_name: String,
     pub player_state: PlayerState,
     pub survived_seconds: i32,
     pub longest_life_seconds: i32,
     pub shortest_life_seconds: i32,
+    pub ranked_lives_remaining: i32,
+    pub unranked_lives_remaining: i32,
     pub events: Vec<TimelineEvent>,
 }
 
+/// The span of a death when the player had no lives left, a week of ticks so it stands out.
+pub const NO_LIVES_SPAN: i32 = -(20 * 60 * 60 * 24 * 7);
+
+/// The lives a player has left at a point in their timeline.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Lives {
+    pub ranked: i32,
+    pub unranked: i32,
+}
+
+impl Lives {
+    pub fn total(&self) -> i32 {
+        self.ranked + self.unranked
+    }
+}
+
+/// Where the player is after all of their events.
+struct Spans {
+    lives: Lives,
+    /// The playtime the player's current life started at.
+    prev_playtime: i32,
+    /// The player died with no lives left and hasn't had an extra life since.
+    ghost: bool,
+}
+
 impl PartialOrd for Timeline {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.survived_seconds.partial_cmp(&other.survived_seconds)
+        Some(self.cmp(other))
     }
 }
 
@@ -33,14 +65,29 @@ impl Ord for Timeline {
     }
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum PlayerState {
     Alive,
     Dead,
+    /// Out of lives but still playing, they have died since their last life.
+    Ghost,
     //Unranked,
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+impl std::str::FromStr for PlayerState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Alive" => Ok(PlayerState::Alive),
+            "Dead" => Ok(PlayerState::Dead),
+            "Ghost" => Ok(PlayerState::Ghost),
+            _ => Err(format!("unknown player state '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum EventType {
     Joined,
     Died,
@@ -49,8 +96,45 @@ pub enum EventType {
     Alive,
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq)]
+impl std::fmt::Display for EventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl EventType {
+    /// The order events with the same stamp and playtime are processed in. An extra life
+    /// at the same time as a death is granted first, so the death uses it.
+    fn priority(&self) -> u8 {
+        match self {
+            EventType::Joined => 0,
+            EventType::ExtraLife => 1,
+            EventType::Offense => 2,
+            EventType::Died => 3,
+            EventType::Alive => 4,
+        }
+    }
+}
+
+impl std::str::FromStr for EventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Joined" => Ok(EventType::Joined),
+            "Died" => Ok(EventType::Died),
+            "ExtraLife" => Ok(EventType::ExtraLife),
+            "Offense" => Ok(EventType::Offense),
+            "Alive" => Ok(EventType::Alive),
+            _ => Err(format!("unknown event type '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct TimelineEvent {
+    /// The id of the death, offense or extra life row, joined and alive events don't have one.
+    pub id: Option<i64>,
     #[serde(with = "date_format")]
     pub stamp : chrono::DateTime<Utc>,
     pub what : EventType,
@@ -61,70 +145,209 @@ pub struct TimelineEvent {
     pub span: i32,
     /// Only useful on a death and alive event and extra life, is the life unranked.
     pub unranked: bool,
+    /// Only on a death, the player had no lives left to lose. Its span is `NO_LIVES_SPAN`.
+    pub no_lives: bool,
+    /// The lives the player has left once this event has happened.
+    pub lives_after: Lives,
 }
 
 impl PartialOrd for TimelineEvent {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.stamp.partial_cmp(&other.stamp)
+        Some(self.cmp(other))
     }
 }
 
+/// Events are ordered by stamp, then playtime, then event type, then row id. Stamps are only
+/// to the second so it is common for a death and an extra life to share one.
 impl Ord for TimelineEvent {
     fn cmp(&self, other: &Self) -> Ordering {
         self.stamp.cmp(&other.stamp)
+            .then_with(|| self.playtime.cmp(&other.playtime))
+            .then_with(|| self.what.priority().cmp(&other.what.priority()))
+            .then_with(|| self.id.cmp(&other.id))
     }
 }
 
 impl Timeline {
 
-    pub async fn build(store: &Store, player: &Player) -> Result<Self, Error> {
+    pub async fn build(store: &dyn Store, player: &Player, rules: &RuleSet, season: Option<&Season>) -> Result<Self, Error> {
         let deaths = store.get_player_deaths(&player.player_uuid).await?;
         let offenses = store.get_player_offenses(&player.player_uuid).await?;
         let extra_lives = store.get_player_extra_lives(&player.player_uuid).await ?;
 
-        let player_state = if deaths.len() >= extra_lives.len() + 3  { PlayerState::Dead } else { PlayerState::Alive };
+        Ok(Self::build_from_parts(player, deaths, offenses, extra_lives, rules, season))
+    }
 
-        let mut events : Vec<TimelineEvent> = Vec::new();
-        for death in &deaths {
-            events.push(Self::death_to_event(death))
-        }
-        for offense in &offenses {
-            events.push(Self::offense_to_event(offense))
-        }
-        for extra_life in &extra_lives {
-            events.push(Self::extra_life_to_event(extra_life))
+    /// Builds every player's timeline with the batched store loaders, players that joined
+    /// after the season ended are left out. Each timeline is only built when the iterator gets
+    /// to it, so they aren't all held at once, but every player's rows are loaded first.
+    pub async fn build_each(store: &dyn Store, rules: &RuleSet, season: Option<&Season>) -> Result<impl Iterator<Item = (Player, Self)> + Send + 'static, Error> {
+        let players = store.get_players().await?;
+        let mut deaths = store.get_deaths().await?;
+        let mut offenses = store.get_offenses().await?;
+        let mut extra_lives = store.get_extra_lives().await?;
+
+        let rules = rules.clone();
+        let season = season.cloned();
+        let included = season.clone();
+
+        Ok(players.into_iter()
+            .filter(move |player| included.as_ref().is_none_or(|s| s.includes(player)))
+            .map(move |player| {
+                let timeline = Self::build_from_parts(
+                    &player,
+                    deaths.remove(&player.player_uuid).unwrap_or_default(),
+                    offenses.remove(&player.player_uuid).unwrap_or_default(),
+                    extra_lives.remove(&player.player_uuid).unwrap_or_default(),
+                    &rules,
+                    season.as_ref(),
+                );
+                (player, timeline)
+            }))
+    }
+
+    /// Builds every player's timeline one player at a time, each player's rows are only loaded
+    /// when the stream gets to them. Slower than `build_each`, but it never holds more than one
+    /// player's rows, for exports that are written as they are built.
+    pub async fn stream_each(store: DynStore, rules: &RuleSet, season: Option<&Season>) -> Result<impl Stream<Item = Result<(Player, Self), Error>> + Send + 'static, Error> {
+        let players: Vec<Player> = store.get_players().await?.into_iter()
+            .filter(|player| season.is_none_or(|s| s.includes(player)))
+            .collect();
+
+        let rules = rules.clone();
+        let season = season.cloned();
+
+        Ok(futures_util::stream::iter(players).then(move |player| {
+            let store = store.clone();
+            let rules = rules.clone();
+            let season = season.clone();
+            async move {
+                let timeline = Self::build(store.as_ref(), &player, &rules, season.as_ref()).await?;
+                Ok((player, timeline))
+            }
+        }))
+    }
+
+    /// Builds a timeline from events that have already been loaded for the player,
+    /// use with the batched store loaders to avoid a query per player.
+    ///
+    /// With a season only the events inside the season are used, and the player is
+    /// treated as joining when the season starts if they joined before it.
+    pub fn build_from_parts(
+        player: &Player,
+        mut deaths: Vec<Death>,
+        mut offenses: Vec<Offense>,
+        mut extra_lives: Vec<ExtraLife>,
+        rules: &RuleSet,
+        season: Option<&Season>,
+    ) -> Self {
+        let started = Instant::now();
+        let mut joined = player.joined;
+        let mut joined_playtime = 0;
+        let mut now = Utc::now();
+        // the playtime the player is alive at now.
+        let mut playtime = player.playtime;
+        if let Some(season) = season {
+            // playtime never resets, so the first life of the season starts at the
+            // last playtime we saw before the season.
+            joined_playtime = deaths.iter().map(|d| (d.stamp, d.playtime))
+                .chain(offenses.iter().map(|o| (o.stamp, o.playtime)))
+                .chain(extra_lives.iter().map(|e| (e.stamp, e.playtime)))
+                .filter(|(stamp, _)| *stamp < season.starts)
+                .map(|(_, playtime)| playtime)
+                .max()
+                .unwrap_or(0);
+
+            deaths.retain(|d| season.contains(d.stamp));
+            offenses.retain(|o| season.contains(o.stamp));
+            extra_lives.retain(|e| season.contains(e.stamp));
+
+            joined = joined.max(season.starts);
+            // we don't know the playtime when the season ended, so the last life of an ended season
+            // stops at the last playtime we saw in it.
+            if let Some(ends) = season.ends.filter(|ends| *ends < now) {
+                now = ends;
+                playtime = deaths.iter().map(|d| d.playtime)
+                    .chain(offenses.iter().map(|o| o.playtime))
+                    .chain(extra_lives.iter().map(|e| e.playtime))
+                    .fold(joined_playtime, i32::max);
+            }
         }
+
+        let mut events = Self::events_from_parts(&deaths, &offenses, &extra_lives);
         events.push(TimelineEvent{
-            stamp: player.joined.clone(),
+            id: None,
+            stamp: joined,
             what: EventType::Joined,
             context: "Joined Hardcore".to_string(),
-            playtime: 0,
+            playtime: joined_playtime,
             span: 0,
             unranked: false,
+            no_lives: false,
+            lives_after: Lives::default(),
         });
+
+        let spans = Self::calculate_spans(&mut events, rules);
+        let lives = spans.lives;
+
+        let player_state = if lives.total() > 0 {
+            PlayerState::Alive
+        } else if spans.ghost {
+            PlayerState::Ghost
+        } else {
+            PlayerState::Dead
+        };
         if player_state == PlayerState::Alive {
             events.push(TimelineEvent{
-                stamp: Utc::now(),
+                id: None,
+                stamp: now,
                 what: EventType::Alive,
                 context: "Player is alive".to_string(),
-                playtime: player.playtime,
-                span: 0,
-                unranked: false,
+                playtime,
+                // how long has the player been alive.
+                span: playtime - spans.prev_playtime,
+                unranked: lives.ranked <= 0,
+                no_lives: false,
+                lives_after: lives,
             });
         }
 
-        Self::calculate_spans(&mut events);
         let (long, short, survived) = Self::find_meta_stats(&events);
         Self::normalize_event_spans(&mut events);
+        metrics::global().observe_timeline_build(started.elapsed());
 
-        Ok(Timeline {
+        Timeline {
             player_name: player.name.clone(),
             player_state,
             events,
             survived_seconds: survived / 20, // normalize to seconds
             longest_life_seconds: long / 20, // normalize to seconds
             shortest_life_seconds: short / 20, // normalize to seconds
-        })
+            ranked_lives_remaining: lives.ranked.max(0),
+            unranked_lives_remaining: lives.unranked.max(0),
+        }
+    }
+
+    /// Ranked and unranked lives the player has left.
+    pub fn lives_remaining(&self) -> i32 {
+        self.ranked_lives_remaining + self.unranked_lives_remaining
+    }
+
+    /// The events for the rows in the order they happened, without the joined and alive
+    /// events. Spans and lives aren't worked out and playtime is still in ticks.
+    pub fn events_from_parts(deaths: &[Death], offenses: &[Offense], extra_lives: &[ExtraLife]) -> Vec<TimelineEvent> {
+        let mut events : Vec<TimelineEvent> = Vec::new();
+        for death in deaths {
+            events.push(Self::death_to_event(death))
+        }
+        for offense in offenses {
+            events.push(Self::offense_to_event(offense))
+        }
+        for extra_life in extra_lives {
+            events.push(Self::extra_life_to_event(extra_life))
+        }
+        events.sort();
+        events
     }
 
     /// Will convert the spans to seconds from ticks. Should call this at the end
@@ -136,32 +359,39 @@ impl Timeline {
         }
     }
 
-    // events must be ordered before this point.
-    fn calculate_spans(events: &mut Vec<TimelineEvent>) {
+    // sorts the events first, see the Ord impl of TimelineEvent for the order.
+    fn calculate_spans(events: &mut [TimelineEvent], rules: &RuleSet) -> Spans {
 
         events.sort();
 
         let mut prev_playtime = 0;
-        let mut ranked_lives = 3;
-        let mut unranked_lives = 0;
+        let mut lives = Lives { ranked: rules.starting_lives, unranked: 0 };
+        let mut extra_lives = 0;
+        let mut ghost = false;
 
         for event in events {
 
             match event.what {
-                EventType::Joined => { /* Nothing to do */ },
+                EventType::Joined => {
+                    // the first life starts at the playtime the player joined with.
+                    prev_playtime = prev_playtime.max(event.playtime);
+                },
                 EventType::Died => {
                     let ranked;
                     // remove life.
-                    if ranked_lives > 0 {
-                        ranked_lives -= 1;
+                    if lives.ranked > 0 {
+                        lives.ranked -= 1;
                         ranked = true;
-                    } else if unranked_lives > 0 {
-                        unranked_lives -=1;
+                    } else if lives.unranked > 0 {
+                        lives.unranked -= 1;
                         ranked = false;
                     } else {
                         event!(target: "hardcore-api", Level::WARN, "Player has no lives but died.");
-                        event.span = 20*60*60*24*7*-1;
+                        event.span = NO_LIVES_SPAN;
                         event.unranked = true;
+                        event.no_lives = true;
+                        event.lives_after = lives;
+                        ghost = true;
                         continue;
                     }
 
@@ -172,41 +402,53 @@ impl Timeline {
                     event.unranked = !ranked;
 
                     // set next playtime
-                    prev_playtime = event.playtime.clone();
+                    prev_playtime = event.playtime;
                 },
-                EventType::Alive => {
-                    // how long did the player live.
-                    event.span = event.playtime - prev_playtime;
-
-                    // unranked life?
-                    event.unranked = ranked_lives <= 0;
-                }
+                EventType::Alive => { /* Added after the spans are calculated */ },
                 EventType::Offense => { /* Nothing to do */ },
                 EventType::ExtraLife => {
+                    // has the player already been given as many extra lives as they can have?
+                    if rules.max_extra_lives.is_some_and(|max| extra_lives >= max) {
+                        event.lives_after = lives;
+                        continue;
+                    }
+                    extra_lives += 1;
+
                     // if the playtime is zero, we don't have data so we can't update playtime
                     // is the player a ghost when they got the extra life?
-                    if event.playtime != 0  &&  ranked_lives + unranked_lives <= 0 {
+                    if event.playtime != 0
+                        && lives.total() <= 0
+                        && rules.ghost == GhostBehaviour::Spectate {
                         // set the playtime to their current time.
-                        prev_playtime = event.playtime.clone();
+                        prev_playtime = event.playtime;
                     }
+                    ghost = false;
 
                     // Is this a paid life?
-                    if event.context == "PAID" {
-                        unranked_lives += 1;
+                    if event.context == "PAID" && !rules.paid_lives_ranked {
+                        lives.unranked += 1;
                         //next_unranked = true;
                         event.unranked = true;
                     } else {
-                        ranked_lives += 1;
+                        lives.ranked += 1;
                     }
                 },
             };
+
+            event.lives_after = lives;
+        }
+
+        Spans {
+            lives,
+            prev_playtime,
+            ghost,
         }
     }
 
 
     /// This function returns the longest life, shortest  life, and total survive time.
     /// ignores any unranked spanns.
-    fn find_meta_stats(events: &Vec<TimelineEvent>) -> (i32, i32, i32) {
+    fn find_meta_stats(events: &[TimelineEvent]) -> (i32, i32, i32) {
 
         let mut long = 0;
         let mut short = i32::MAX;
@@ -224,10 +466,10 @@ impl Timeline {
             survive_time += event.span;
 
             if event.span > long {
-                long = event.span.clone();
+                long = event.span;
             }
             if event.span < short {
-                short = event.span.clone();
+                short = event.span;
             }
         }
 
@@ -236,34 +478,286 @@ impl Timeline {
 
     fn death_to_event(a: &Death) -> TimelineEvent {
         TimelineEvent {
-            stamp: a.stamp.clone(),
+            id: Some(a.id),
+            stamp: a.stamp,
             what: EventType::Died,
             context: a.reason.clone(),
             playtime: a.playtime,
             span: 0,
             unranked: false,
+            no_lives: false,
+            lives_after: Lives::default(),
         }
     }
 
     fn offense_to_event(a: &Offense) -> TimelineEvent {
         TimelineEvent {
-            stamp: a.stamp.clone(),
+            id: Some(a.id),
+            stamp: a.stamp,
             what: EventType::Offense,
             context: a.reason.clone(),
             playtime: a.playtime,
             span: 0,
             unranked: false,
+            no_lives: false,
+            lives_after: Lives::default(),
         }
     }
 
     fn extra_life_to_event(a: &ExtraLife) -> TimelineEvent {
         TimelineEvent {
-            stamp: a.stamp.clone(),
+            id: Some(a.id),
+            stamp: a.stamp,
             what: EventType::ExtraLife,
             context: a.reason.clone(),
             playtime: a.playtime,
             span: 0,
             unranked: false,
+            no_lives: false,
+            lives_after: Lives::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use chrono::{DateTime, Utc};
+    use crate::store::memory::{Fixture, MemoryStore};
+    use crate::test_support::{at, death, extra_life, player};
+    use super::*;
+
+    fn paid_life(id: i64, stamp: DateTime<Utc>, playtime: i32) -> ExtraLife {
+        ExtraLife { reason: "PAID".to_string(), ..extra_life(id, stamp, playtime) }
+    }
+
+    fn season(ends: Option<DateTime<Utc>>) -> Season {
+        Season { id: 1, name: "one".to_string(), starts: at(100), ends, rules: None }
+    }
+
+    fn one_life() -> RuleSet {
+        RuleSet { starting_lives: 1, ..RuleSet::default() }
+    }
+
+    fn ids(timeline: &Timeline) -> Vec<(EventType, Option<i64>)> {
+        timeline.events.iter().map(|e| (e.what, e.id)).collect()
+    }
+
+    #[test]
+    fn extra_life_at_the_same_stamp_is_granted_before_the_death() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(20), 4000)];
+        let extra_lives = vec![extra_life(3, at(20), 4000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &one_life(), None);
+
+        assert_eq!(ids(&timeline), vec![
+            (EventType::Joined, None),
+            (EventType::Died, Some(1)),
+            (EventType::ExtraLife, Some(3)),
+            (EventType::Died, Some(2)),
+        ]);
+        // the second death used the extra life instead of being a death with no lives left.
+        let last = timeline.events.last().unwrap();
+        assert!(last.span >= 0);
+        assert_eq!(timeline.player_state, PlayerState::Dead);
+    }
+
+    #[test]
+    fn the_same_result_whatever_order_the_rows_are_loaded_in() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(20), 4000)];
+        let extra_lives = vec![extra_life(3, at(20), 4000)];
+        let mut reversed = deaths.clone();
+        reversed.reverse();
+
+        let a = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives.clone(), &one_life(), None);
+        let b = Timeline::build_from_parts(&player(), reversed, vec![], extra_lives, &one_life(), None);
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn playtime_breaks_a_stamp_tie() {
+        let deaths = vec![death(1, at(10), 6000), death(2, at(10), 2000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], vec![], &RuleSet::default(), None);
+
+        assert_eq!(ids(&timeline)[1..3], [(EventType::Died, Some(2)), (EventType::Died, Some(1))]);
+        assert_eq!(timeline.events[1].span, 2000 / 20);
+        assert_eq!(timeline.events[2].span, 4000 / 20);
+    }
+
+    #[test]
+    fn id_breaks_a_stamp_and_playtime_tie() {
+        let deaths = vec![death(5, at(10), 2000), death(4, at(10), 2000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], vec![], &RuleSet::default(), None);
+
+        assert_eq!(ids(&timeline)[1..3], [(EventType::Died, Some(4)), (EventType::Died, Some(5))]);
+    }
+
+    #[test]
+    fn joined_comes_before_an_event_at_the_same_stamp() {
+        let deaths = vec![death(1, at(0), 0)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], vec![], &RuleSet::default(), None);
+
+        assert_eq!(ids(&timeline)[0..2], [(EventType::Joined, None), (EventType::Died, Some(1))]);
+    }
+
+    #[test]
+    fn deaths_order_by_stamp_then_playtime_then_id() {
+        let mut deaths = [
+            death(3, at(10), 2000),
+            death(2, at(10), 1000),
+            death(1, at(10), 2000),
+            death(4, at(5), 9000),
+        ];
+        deaths.sort();
+
+        assert_eq!(deaths.iter().map(|d| d.id).collect::<Vec<_>>(), vec![4, 2, 1, 3]);
+    }
+
+    #[test]
+    fn starting_lives_are_ranked_lives() {
+        let deaths = vec![death(1, at(10), 2000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths.clone(), vec![], vec![], &RuleSet::default(), None);
+        assert_eq!(timeline.player_state, PlayerState::Alive);
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], vec![], &one_life(), None);
+        assert_eq!(timeline.player_state, PlayerState::Dead);
+    }
+
+    #[test]
+    fn paid_lives_are_unranked_unless_the_rules_say_otherwise() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(30), 5000)];
+        let extra_lives = vec![paid_life(3, at(20), 3000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths.clone(), vec![], extra_lives.clone(), &one_life(), None);
+        assert_eq!(timeline.events[2].what, EventType::ExtraLife);
+        assert!(timeline.events[2].unranked);
+        // the life after the paid life doesn't count towards the time survived.
+        assert!(timeline.events[3].unranked);
+        assert_eq!(timeline.survived_seconds, 2000 / 20);
+
+        let rules = RuleSet { paid_lives_ranked: true, ..one_life() };
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &rules, None);
+        assert!(!timeline.events[2].unranked);
+        assert!(!timeline.events[3].unranked);
+        assert_eq!(timeline.survived_seconds, 2000 / 20 + 2000 / 20);
+    }
+
+    #[test]
+    fn every_event_has_the_lives_left_after_it() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(30), 5000)];
+        let extra_lives = vec![paid_life(3, at(20), 3000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &RuleSet::default(), None);
+
+        let lives: Vec<(i32, i32)> = timeline.events.iter().map(|e| (e.lives_after.ranked, e.lives_after.unranked)).collect();
+        assert_eq!(lives, vec![(3, 0), (2, 0), (2, 1), (1, 1), (1, 1)]);
+        assert_eq!(timeline.ranked_lives_remaining, 1);
+        assert_eq!(timeline.unranked_lives_remaining, 1);
+    }
+
+    #[test]
+    fn a_player_that_dies_with_no_lives_left_is_a_ghost() {
+        let deaths = vec![death(1, at(10), 2000), death(2, at(20), 4000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths[..1].to_vec(), vec![], vec![], &one_life(), None);
+        assert_eq!(timeline.player_state, PlayerState::Dead);
+
+        let timeline = Timeline::build_from_parts(&player(), deaths.clone(), vec![], vec![], &one_life(), None);
+        assert_eq!(timeline.player_state, PlayerState::Ghost);
+        assert!(timeline.events[2].no_lives);
+        assert!(!timeline.events[1].no_lives);
+        assert_eq!(timeline.events[2].span, NO_LIVES_SPAN / 20);
+        assert_eq!(timeline.events[2].lives_after, Lives::default());
+        assert_eq!(timeline.lives_remaining(), 0);
+
+        // an extra life brings them back, dying on it is an elimination again.
+        let deaths = [deaths, vec![death(4, at(40), 6000)]].concat();
+        let extra_lives = vec![extra_life(3, at(30), 5000)];
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &one_life(), None);
+        assert_eq!(timeline.player_state, PlayerState::Dead);
+    }
+
+    #[test]
+    fn extra_lives_past_the_max_are_ignored() {
+        let extra_lives = vec![extra_life(1, at(10), 1000), extra_life(2, at(20), 2000), extra_life(3, at(30), 3000)];
+        let deaths = vec![death(4, at(40), 4000), death(5, at(50), 5000), death(6, at(60), 6000)];
+        let rules = RuleSet { max_extra_lives: Some(2), ..one_life() };
+
+        let timeline = Timeline::build_from_parts(&player(), deaths.clone(), vec![], extra_lives.clone(), &rules, None);
+        assert_eq!(timeline.player_state, PlayerState::Dead);
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &one_life(), None);
+        assert_eq!(timeline.player_state, PlayerState::Alive);
+    }
+
+    #[test]
+    fn a_season_starts_inclusive_and_ends_exclusive() {
+        let ended = season(Some(at(1000)));
+
+        assert!(!ended.contains(at(99)));
+        assert!(ended.contains(at(100)));
+        assert!(ended.contains(at(999)));
+        assert!(!ended.contains(at(1000)));
+        assert!(season(None).contains(at(1_000_000)));
+    }
+
+    #[test]
+    fn a_season_only_has_the_events_inside_it() {
+        let deaths = vec![death(1, at(50), 2000), death(2, at(200), 5000), death(4, at(2000), 9000)];
+        let extra_lives = vec![extra_life(3, at(500), 6000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &RuleSet::default(), Some(&season(None)));
+
+        assert_eq!(ids(&timeline), vec![
+            (EventType::Joined, None),
+            (EventType::Died, Some(2)),
+            (EventType::ExtraLife, Some(3)),
+            (EventType::Died, Some(4)),
+            (EventType::Alive, None),
+        ]);
+        // the player joins the season at its start, with the last playtime before it.
+        assert_eq!(timeline.events[0].stamp, at(100));
+        assert_eq!(timeline.events[1].span, (5000 - 2000) / 20);
+        // a running season is still being played.
+        assert_eq!(timeline.events[4].span, (100_000 - 9000) / 20);
+    }
+
+    #[test]
+    fn an_ended_season_stops_at_the_last_playtime_in_it() {
+        let deaths = vec![death(1, at(50), 2000), death(2, at(200), 5000), death(4, at(2000), 9000)];
+        let extra_lives = vec![extra_life(3, at(500), 6000)];
+
+        let timeline = Timeline::build_from_parts(&player(), deaths, vec![], extra_lives, &RuleSet::default(), Some(&season(Some(at(1000)))));
+
+        let alive = timeline.events.last().unwrap();
+        assert_eq!(alive.what, EventType::Alive);
+        assert_eq!(alive.stamp, at(1000));
+        // playtime after the season ended isn't counted.
+        assert_eq!(alive.playtime, 6000 / 20);
+        assert_eq!(alive.span, (6000 - 5000) / 20);
+        assert_eq!(timeline.survived_seconds, (6000 - 2000) / 20);
+    }
+
+    #[tokio::test]
+    async fn streamed_timelines_are_the_same_as_the_batched_ones() {
+        let fixture = Fixture { players: vec![player()], deaths: vec![death(1, at(10), 2000)], ..Fixture::default() };
+        let store: DynStore = Arc::new(MemoryStore::new(fixture));
+
+        let built: Vec<Timeline> = Timeline::build_each(store.as_ref(), &one_life(), None).await.unwrap()
+            .map(|(_, timeline)| timeline)
+            .collect();
+        let streamed: Vec<Timeline> = Timeline::stream_each(store, &one_life(), None).await.unwrap()
+            .map(|res| res.unwrap().1)
+            .collect()
+            .await;
+
+        // dead, so there is no alive event stamped with now.
+        assert_eq!(streamed, built);
+        assert_eq!(streamed.len(), 1);
+    }
+}
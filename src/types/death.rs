@@ -1,28 +1,55 @@
 use std::cmp::Ordering;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use chrono::serde::ts_seconds;
+use crate::errors::Error;
+use crate::types::{stamp, validate};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct Death {
     pub id: i64,
-    pub player_uuid: String,
+    pub player_uuid: Uuid,
     #[serde(with = "ts_seconds")]
     pub stamp: DateTime<Utc>,
     pub playtime: i32,
     pub reason: String,
 }
 
-// it is possible for two events to have the exact same stamp... which would break some rules.
+/// A death posted by the plugin, the id is assigned by the database.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewDeath {
+    pub player_uuid: Uuid,
+    #[serde(deserialize_with = "stamp::deserialize")]
+    pub stamp: DateTime<Utc>,
+    pub playtime: i32,
+    pub reason: String,
+    /// Written by an import, its change isn't turned into a live event.
+    #[serde(skip)]
+    pub imported: bool,
+}
+
+impl NewDeath {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate::stamp(&self.stamp)?;
+        validate::playtime(self.playtime)?;
+        validate::reason(&self.reason)
+    }
+}
+
+// stamps are only to the second, so two deaths can have the same stamp. playtime and then
+// the id break the tie so the order is always the same.
 
 impl PartialOrd for Death {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.stamp.partial_cmp(&other.stamp) // should consider playtime
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Death {
     fn cmp(&self, other: &Self) -> Ordering {
         self.stamp.cmp(&other.stamp)
+            .then_with(|| self.playtime.cmp(&other.playtime))
+            .then_with(|| self.id.cmp(&other.id))
     }
 }
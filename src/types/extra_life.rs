@@ -1,11 +1,35 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::errors::Error;
+use crate::types::{stamp, validate};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExtraLife {
     pub id: i64,
-    pub player_uuid: String,
+    pub player_uuid: Uuid,
     pub stamp: DateTime<Utc>,
     pub reason: String,
     pub playtime: i32,
-}
\ No newline at end of file
+}
+
+/// An extra life posted by the plugin, the id is assigned by the database.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewExtraLife {
+    pub player_uuid: Uuid,
+    #[serde(deserialize_with = "stamp::deserialize")]
+    pub stamp: DateTime<Utc>,
+    pub reason: String,
+    pub playtime: i32,
+    /// Written by an import, its change isn't turned into a live event.
+    #[serde(skip)]
+    pub imported: bool,
+}
+
+impl NewExtraLife {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate::stamp(&self.stamp)?;
+        validate::playtime(self.playtime)?;
+        validate::reason(&self.reason)
+    }
+}
@@ -1,4 +1,8 @@
 pub mod death;
 pub mod extra_life;
 pub mod offense;
-pub mod player;
\ No newline at end of file
+pub mod player;
+pub mod season;
+pub mod stamp;
+pub mod validate;
+pub mod webhook;
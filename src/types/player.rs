@@ -1,12 +1,56 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::errors::Error;
+use crate::types::{stamp, validate};
 //use sqlx::types::chrono;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Player {
-    pub player_uuid: String,
+    pub player_uuid: Uuid,
     pub name: String,
     //pub joined: chrono::DataTime<chrono::Utc>,
     pub joined: DateTime<Utc>,
     pub playtime: i32,
-}
\ No newline at end of file
+}
+
+/// The player fields the plugin can set, the uuid comes from the path.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PlayerUpdate {
+    pub name: String,
+    #[serde(deserialize_with = "stamp::deserialize")]
+    pub joined: DateTime<Utc>,
+    pub playtime: i32,
+    /// Written by an import, its change isn't turned into a live event.
+    #[serde(skip)]
+    pub imported: bool,
+}
+
+impl PlayerUpdate {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate::name(&self.name)?;
+        validate::stamp(&self.joined)?;
+        validate::playtime(self.playtime)
+    }
+}
+
+/// A player with their uuid, for imports where there is no path to take it from.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewPlayer {
+    pub player_uuid: Uuid,
+    pub name: String,
+    #[serde(deserialize_with = "stamp::deserialize")]
+    pub joined: DateTime<Utc>,
+    pub playtime: i32,
+}
+
+impl NewPlayer {
+    pub fn update(&self) -> PlayerUpdate {
+        PlayerUpdate {
+            name: self.name.clone(),
+            joined: self.joined,
+            playtime: self.playtime,
+            imported: false,
+        }
+    }
+}
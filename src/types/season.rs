@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::errors::Error;
+use crate::rules::RuleSet;
+use crate::types::player::Player;
+use crate::types::stamp;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Season {
+    pub id: i64,
+    pub name: String,
+    pub starts: DateTime<Utc>,
+    /// Empty while the season is still running.
+    pub ends: Option<DateTime<Utc>>,
+    /// The rules for the season, the server's rules are used when empty.
+    pub rules: Option<RuleSet>,
+}
+
+impl Season {
+    pub fn contains(&self, stamp: DateTime<Utc>) -> bool {
+        stamp >= self.starts && self.ends.is_none_or(|ends| stamp < ends)
+    }
+
+    /// Players that joined after the season ended weren't part of it.
+    pub fn includes(&self, player: &Player) -> bool {
+        self.ends.is_none_or(|ends| player.joined < ends)
+    }
+
+    pub fn rules_or(&self, rules: &RuleSet) -> RuleSet {
+        self.rules.clone().unwrap_or_else(|| rules.clone())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewSeason {
+    pub name: String,
+    #[serde(deserialize_with = "stamp::deserialize")]
+    pub starts: DateTime<Utc>,
+    #[serde(default, deserialize_with = "stamp::deserialize_option")]
+    pub ends: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub rules: Option<RuleSet>,
+}
+
+impl NewSeason {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidInput("name must not be empty".to_string()));
+        }
+        if self.ends.is_some_and(|ends| ends <= self.starts) {
+            return Err(Error::InvalidInput("ends must be after starts".to_string()));
+        }
+        Ok(())
+    }
+}
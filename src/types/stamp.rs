@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+/// A stamp that was posted or imported, RFC 3339 or unix seconds.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Stamp {
+    Seconds(i64),
+    Text(String),
+}
+
+impl Stamp {
+    pub fn parse(&self) -> Result<DateTime<Utc>, String> {
+        match self {
+            Stamp::Seconds(seconds) => DateTime::from_timestamp(*seconds, 0)
+                .ok_or_else(|| format!("stamp {} is out of range", seconds)),
+            Stamp::Text(text) => match text.parse::<i64>() {
+                Ok(seconds) => Stamp::Seconds(seconds).parse(),
+                Err(_) => DateTime::parse_from_rfc3339(text)
+                    .map(|stamp| stamp.with_timezone(&Utc))
+                    .map_err(|e| format!("stamp '{}' is not RFC 3339 or unix seconds: {}", text, e)),
+            },
+        }
+    }
+}
+
+/// Every write endpoint takes stamps the same way, use with
+/// `#[serde(deserialize_with = "stamp::deserialize")]`.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Stamp::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+/// The same as `deserialize` for stamps that can be left out, use with `#[serde(default)]` as well.
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Stamp>::deserialize(deserializer)?
+        .map(|stamp| stamp.parse())
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
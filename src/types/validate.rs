@@ -0,0 +1,34 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::errors::Error;
+
+/// How far in the future a stamp can be, to allow for clock drift between servers.
+const MAX_CLOCK_DRIFT_SECONDS: i64 = 5 * 60;
+
+pub fn stamp(stamp: &DateTime<Utc>) -> Result<(), Error> {
+    if *stamp > Utc::now() + Duration::seconds(MAX_CLOCK_DRIFT_SECONDS) {
+        return Err(Error::InvalidInput("stamp is in the future".to_string()));
+    }
+    Ok(())
+}
+
+pub fn playtime(playtime: i32) -> Result<(), Error> {
+    if playtime < 0 {
+        return Err(Error::InvalidInput("playtime can not be negative".to_string()));
+    }
+    Ok(())
+}
+
+pub fn reason(reason: &str) -> Result<(), Error> {
+    if reason.trim().is_empty() {
+        return Err(Error::InvalidInput("reason can not be empty".to_string()));
+    }
+    Ok(())
+}
+
+/// Minecraft names are 3 to 16 letters, numbers and underscores.
+pub fn name(name: &str) -> Result<(), Error> {
+    if name.len() < 3 || name.len() > 16 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::InvalidInput(format!("'{}' is not a valid player name", name)));
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::errors::Error;
+use crate::timeline::EventType;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Used to sign every payload, only returned when the webhook is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// The events the subscriber wants, empty means every event.
+    pub event_types: Vec<EventType>,
+    pub created: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn wants(&self, what: EventType) -> bool {
+        self.event_types.is_empty() || self.event_types.contains(&what)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub event_types: Vec<EventType>,
+}
+
+impl NewWebhook {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+            return Err(Error::InvalidInput("url must be http or https".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// A delivery that failed after every retry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub payload: String,
+    pub error: String,
+    pub attempts: i32,
+    pub created: DateTime<Utc>,
+}